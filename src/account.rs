@@ -0,0 +1,231 @@
+#![allow(non_snake_case)]
+use dioxus::prelude::*;
+use serde::Deserialize;
+use reality::protocol::{NewToken, TokenInfo, TokenScope};
+use crate::editor::history::format_time;
+
+// matches the server's auth::AuthUser
+#[derive(Deserialize, Clone, PartialEq)]
+pub struct Me {
+    pub id:       String,
+    pub username: String,
+}
+
+// the session cookie is httponly, so asking the server is the only way to know who we are
+pub async fn fetch_me() -> Option<Me> {
+    let resp = gloo_net::http::Request::get("/api/auth/me").send().await.ok()?;
+    if !resp.ok() { return None; }
+    resp.json().await.ok()
+}
+
+// register and login take the same body, the error text comes straight from the server
+async fn submit(path: &str, username: &str, password: &str) -> Result<Me, String> {
+    let body = serde_json::json!({ "username": username, "password": password });
+    let resp = gloo_net::http::Request::post(path)
+        .json(&body).map_err(|e| e.to_string())?
+        .send().await.map_err(|e| e.to_string())?;
+    if !resp.ok() {
+        return Err(resp.text().await.unwrap_or_else(|_| "something went wrong".to_string()));
+    }
+    resp.json().await.map_err(|e| e.to_string())
+}
+
+async fn logout() {
+    let _ = gloo_net::http::Request::post("/api/auth/logout").send().await;
+}
+
+// sign in / sign up box for the landing page, collapses to "signed in as" once you're in
+#[component]
+pub fn AccountBox() -> Element {
+    let mut me       = use_signal(|| None::<Me>);
+    let mut username = use_signal(String::new);
+    let mut password = use_signal(String::new);
+    let mut error    = use_signal(String::new);
+
+    use_effect(move || {
+        wasm_bindgen_futures::spawn_local(async move {
+            me.set(fetch_me().await);
+        });
+    });
+
+    let send = move |path: &'static str| {
+        let (u, p) = (username.read().clone(), password.read().clone());
+        wasm_bindgen_futures::spawn_local(async move {
+            match submit(path, &u, &p).await {
+                Ok(user) => { me.set(Some(user)); password.set(String::new()); error.set(String::new()); }
+                Err(e)   => error.set(e),
+            }
+        });
+    };
+
+    if let Some(user) = me.read().clone() {
+        return rsx! {
+            div { style: "display:flex;flex-direction:column;gap:0.5rem;align-items:center;font-size:0.9rem;",
+                div { style: "display:flex;gap:0.5rem;align-items:center;",
+                    span { "Signed in as {user.username}" }
+                    button {
+                        onclick: move |_| {
+                            wasm_bindgen_futures::spawn_local(async move {
+                                logout().await;
+                                me.set(None);
+                            });
+                        },
+                        "Log out"
+                    }
+                }
+                details {
+                    summary { style: "cursor:pointer;", "API tokens" }
+                    TokenManager { doc_id: None }
+                }
+            }
+        };
+    }
+
+    rsx! {
+        div { style: "display:flex;flex-direction:column;gap:0.5rem;align-items:center;font-size:0.9rem;",
+            div { style: "display:flex;gap:0.5rem;",
+                input {
+                    placeholder: "Username",
+                    value: "{username}",
+                    oninput: move |e| username.set(e.value()),
+                }
+                input {
+                    r#type: "password",
+                    placeholder: "Password",
+                    value: "{password}",
+                    oninput: move |e| password.set(e.value()),
+                }
+                button { onclick: move |_| send("/api/auth/login"), "Log in" }
+                button { onclick: move |_| send("/api/auth/register"), "Sign up" }
+            }
+            if !error.read().is_empty() {
+                span { style: "color:#c0392b;", "{error}" }
+            }
+        }
+    }
+}
+
+// /api/tokens for your own tokens, /api/doc/:id/tokens for ones that only open that doc
+fn tokens_url(doc_id: Option<&str>) -> String {
+    match doc_id {
+        Some(id) => format!("/api/doc/{id}/tokens"),
+        None     => "/api/tokens".to_string(),
+    }
+}
+
+async fn fetch_tokens(doc_id: Option<&str>) -> Option<Vec<TokenInfo>> {
+    let resp = gloo_net::http::Request::get(&tokens_url(doc_id)).send().await.ok()?;
+    if !resp.ok() { return None; }
+    resp.json().await.ok()
+}
+
+async fn create_token(doc_id: Option<&str>, name: &str, scope: TokenScope) -> Result<NewToken, String> {
+    let body = serde_json::json!({ "name": name, "scope": scope });
+    let resp = gloo_net::http::Request::post(&tokens_url(doc_id))
+        .json(&body).map_err(|e| e.to_string())?
+        .send().await.map_err(|e| e.to_string())?;
+    if !resp.ok() {
+        return Err(resp.text().await.unwrap_or_else(|_| "couldn't create the token".to_string()));
+    }
+    resp.json().await.map_err(|e| e.to_string())
+}
+
+async fn revoke_token(doc_id: Option<&str>, token_id: &str) -> bool {
+    let url = format!("{}/{token_id}", tokens_url(doc_id));
+    matches!(gloo_net::http::Request::delete(&url).send().await, Ok(resp) if resp.ok())
+}
+
+// list / create / revoke api tokens, the secret is only shown once right after creating it
+#[component]
+pub fn TokenManager(doc_id: Option<String>) -> Element {
+    let mut tokens = use_signal(Vec::<TokenInfo>::new);
+    let mut name   = use_signal(String::new);
+    let mut scope  = use_signal(|| TokenScope::Read);
+    let mut fresh  = use_signal(|| None::<String>);
+    let mut error  = use_signal(String::new);
+
+    use_effect({
+        let doc_id = doc_id.clone();
+        move || {
+            let doc_id = doc_id.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                tokens.set(fetch_tokens(doc_id.as_deref()).await.unwrap_or_default());
+            });
+        }
+    });
+
+    rsx! {
+        div { style: "display:flex;flex-direction:column;gap:0.35rem;font-size:0.85rem;padding-top:0.35rem;",
+            for token in tokens.read().iter().cloned() {
+                div { key: "{token.id}", style: "display:flex;align-items:center;gap:0.5rem;",
+                    span { style: "flex:1;", "{token.name}" }
+                    span { style: "opacity:0.6;",
+                        if token.scope == TokenScope::Write { "read + write" } else { "read" }
+                    }
+                    span { style: "opacity:0.6;", "{format_time(token.created)}" }
+                    button {
+                        style: "background:none;border:none;cursor:pointer;color:#c00;",
+                        title: "Revoke",
+                        onclick: {
+                            let doc_id = doc_id.clone();
+                            move |_| {
+                                let doc_id = doc_id.clone();
+                                let token_id = token.id.clone();
+                                wasm_bindgen_futures::spawn_local(async move {
+                                    if revoke_token(doc_id.as_deref(), &token_id).await {
+                                        tokens.write().retain(|t| t.id != token_id);
+                                    }
+                                });
+                            }
+                        },
+                        "✕"
+                    }
+                }
+            }
+            div { style: "display:flex;gap:0.5rem;",
+                input {
+                    style: "flex:1;padding:0.3rem 0.5rem;border:1px solid #ccc;border-radius:4px;",
+                    placeholder: "Token name, e.g. ci",
+                    value: "{name}",
+                    oninput: move |e| name.set(e.value()),
+                }
+                select {
+                    title: "Write tokens can edit text, sharing and passwords are left to people",
+                    onchange: move |e| scope.set(if e.value() == "write" { TokenScope::Write } else { TokenScope::Read }),
+                    option { value: "read", "read" }
+                    option { value: "write", "read + write" }
+                }
+                button {
+                    onclick: {
+                        let doc_id = doc_id.clone();
+                        move |_| {
+                            let doc_id = doc_id.clone();
+                            let (n, s) = (name.read().clone(), scope());
+                            wasm_bindgen_futures::spawn_local(async move {
+                                match create_token(doc_id.as_deref(), &n, s).await {
+                                    Ok(created) => {
+                                        fresh.set(Some(created.token));
+                                        tokens.write().push(created.info);
+                                        name.set(String::new());
+                                        error.set(String::new());
+                                    }
+                                    Err(e) => error.set(e),
+                                }
+                            });
+                        }
+                    },
+                    "Create"
+                }
+            }
+            if let Some(token) = fresh.read().as_ref() {
+                div { style: "background:#fffbe6;border:1px solid #e6d98a;border-radius:4px;padding:0.4rem;",
+                    "Copy this now, it won't be shown again:"
+                    code { style: "display:block;word-break:break-all;user-select:all;margin-top:0.25rem;", "{token}" }
+                }
+            }
+            if !error.read().is_empty() {
+                span { style: "color:#c0392b;", "{error}" }
+            }
+        }
+    }
+}
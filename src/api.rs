@@ -0,0 +1,487 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    http::header,
+    routing::{delete, get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use reality::diff;
+use reality::protocol::{safe_file_name, Grant, Role, ServerMsg, Sharing};
+use crate::auth::Caller;
+use crate::meta::Tag;
+use crate::state::{now_secs, AppState, Room};
+
+// plain http routes for things that aren't live editing, history and such
+pub fn api_router(state: AppState) -> Router {
+    Router::new()
+        .route("/api/doc/:id/history", get(history))
+        .route("/api/doc/:id/at", get(text_at))
+        .route("/api/doc/:id/restore", post(restore))
+        .route("/api/doc/:id/append", post(append))
+        .route("/api/doc/:id/diff", get(diff_versions))
+        .route("/api/doc/:id/compare/:other", get(compare_docs))
+        .route("/api/doc/:id/blame", get(blame))
+        .route("/api/doc/:id/view-token", get(view_token))
+        .route("/api/doc/:id/sharing", get(get_sharing).put(set_sharing))
+        .route("/api/doc/:id/claim", post(claim))
+        .route("/api/doc/:id/tags", get(list_tags).post(create_tag))
+        .route("/api/doc/:id/tags/:name", delete(delete_tag))
+        .route("/api/doc/:id/tags/:name/text", get(tag_text))
+        .with_state(state)
+}
+
+#[derive(Deserialize)]
+struct HeadsQuery {
+    // comma separated change hashes, same ones history hands out
+    heads: String,
+}
+
+// from/to are comma separated heads, leaving one out means the current text
+#[derive(Deserialize)]
+struct DiffQuery {
+    from: Option<String>,
+    to:   Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RestoreBody {
+    heads: Vec<String>,
+}
+
+// one colored run in the blame view, name is empty when we never learned who the actor was
+#[derive(Serialize)]
+struct BlameSpan {
+    text:  String,
+    actor: String,
+    name:  String,
+    time:  i64,
+}
+
+// heads left out means tag whatever the doc looks like right now
+#[derive(Deserialize)]
+struct TagBody {
+    name:  String,
+    heads: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+struct ExportQuery {
+    #[serde(default)]
+    download: bool,
+}
+
+// owner only, replaces the whole acl with what the dialog sends
+#[derive(Deserialize)]
+struct SharingBody {
+    link_role: Option<Role>,
+    grants:    Vec<Grant>,
+}
+
+// the room if whoever sent the request has at least `min` on it
+pub async fn authorize(state: &AppState, id: &str, caller: &Caller, min: Role) -> Result<Room, Response> {
+    let Some(room) = state.get_room(id) else {
+        return Err(StatusCode::NOT_FOUND.into_response());
+    };
+    let meta = room.meta.lock().await;
+    match caller.role(id, &meta) {
+        Some(role) if role >= min => {}
+        _ => return Err(StatusCode::FORBIDDEN.into_response()),
+    }
+    if caller.needs_password(id, &meta, &state.passes) {
+        return Err((StatusCode::UNAUTHORIZED, "password required").into_response());
+    }
+    drop(meta);
+    Ok(room)
+}
+
+fn split_heads(heads: &str) -> Vec<String> {
+    heads
+        .split(',')
+        .filter(|h| !h.is_empty())
+        .map(|h| h.to_string())
+        .collect()
+}
+
+async fn history(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    caller: Caller,
+) -> impl IntoResponse {
+    let room = match authorize(&state, &id, &caller, Role::Viewer).await {
+        Ok(room) => room,
+        Err(resp) => return resp,
+    };
+    let sessions = room.doc.lock().await.history();
+    Json(sessions).into_response()
+}
+
+async fn text_at(
+    Path(id): Path<String>,
+    Query(q): Query<HeadsQuery>,
+    State(state): State<AppState>,
+    caller: Caller,
+) -> impl IntoResponse {
+    let room = match authorize(&state, &id, &caller, Role::Viewer).await {
+        Ok(room) => room,
+        Err(resp) => return resp,
+    };
+    let text = room.doc.lock().await.text_at(&split_heads(&q.heads));
+    match text {
+        Some(text) => text.into_response(),
+        None       => (StatusCode::BAD_REQUEST, "unknown heads").into_response(),
+    }
+}
+
+// puts the doc back to an older version, everyone in the room gets it like a normal edit
+async fn restore(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    caller: Caller,
+    Json(body): Json<RestoreBody>,
+) -> impl IntoResponse {
+    let room = match authorize(&state, &id, &caller, Role::Editor).await {
+        Ok(room) => room,
+        Err(resp) => return resp,
+    };
+    if room.history_full(&state.limits) {
+        return (StatusCode::PAYLOAD_TOO_LARGE, state.limits.history_full_message()).into_response();
+    }
+    let (text, full_doc) = {
+        let mut doc = room.doc.lock().await;
+        // the limit may have come down since that version was written
+        if doc.text_at(&body.heads).is_some_and(|old| old.chars().count() > state.limits.max_doc_chars) {
+            return (StatusCode::PAYLOAD_TOO_LARGE, state.limits.too_long_message()).into_response();
+        }
+        let Some(text) = doc.restore(&body.heads, now_secs()) else {
+            return (StatusCode::BAD_REQUEST, "unknown heads").into_response();
+        };
+        (text, doc.save())
+    };
+    room.persist(&id, text.clone(), full_doc.clone());
+    let payload = serde_json::to_string(&ServerMsg {
+        sender_id: "restore".to_string(),
+        text:      text.clone(),
+        full_doc,
+        error:     None,
+    }).unwrap();
+    let _ = room.tx.send(payload);
+    text.into_response()
+}
+
+// for scripts, the body is plain text stuck on the end of the doc on a line of its own
+async fn append(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    caller: Caller,
+    body: String,
+) -> impl IntoResponse {
+    let room = match authorize(&state, &id, &caller, Role::Editor).await {
+        Ok(room) => room,
+        Err(resp) => return resp,
+    };
+    if body.is_empty() {
+        return StatusCode::BAD_REQUEST.into_response();
+    }
+    if room.history_full(&state.limits) {
+        return (StatusCode::PAYLOAD_TOO_LARGE, state.limits.history_full_message()).into_response();
+    }
+    let (text, full_doc) = {
+        let mut doc = room.doc.lock().await;
+        let current = doc.get_text();
+        let sep = if current.is_empty() || current.ends_with('\n') { "" } else { "\n" };
+        let (at, added) = (current.chars().count(), format!("{sep}{body}"));
+        if at + added.chars().count() > state.limits.max_doc_chars {
+            return (StatusCode::PAYLOAD_TOO_LARGE, state.limits.too_long_message()).into_response();
+        }
+        doc.splice_text(at, 0, &added, now_secs());
+        (doc.get_text(), doc.save())
+    };
+    room.persist(&id, text.clone(), full_doc.clone());
+    let payload = serde_json::to_string(&ServerMsg {
+        sender_id: "api".to_string(),
+        text,
+        full_doc,
+        error: None,
+    }).unwrap();
+    let _ = room.tx.send(payload);
+    StatusCode::NO_CONTENT.into_response()
+}
+
+async fn diff_versions(
+    Path(id): Path<String>,
+    Query(q): Query<DiffQuery>,
+    State(state): State<AppState>,
+    caller: Caller,
+) -> impl IntoResponse {
+    let room = match authorize(&state, &id, &caller, Role::Viewer).await {
+        Ok(room) => room,
+        Err(resp) => return resp,
+    };
+    let (old, new) = {
+        let mut doc = room.doc.lock().await;
+        let mut version = |heads: &Option<String>| match heads {
+            Some(h) => doc.text_at(&split_heads(h)),
+            None    => Some(doc.get_text()),
+        };
+        (version(&q.from), version(&q.to))
+    };
+    match (old, new) {
+        (Some(old), Some(new)) => Json(diff::hunks(&old, &new)).into_response(),
+        _                      => (StatusCode::BAD_REQUEST, "unknown heads").into_response(),
+    }
+}
+
+// current text of one doc against another
+async fn compare_docs(
+    Path((id, other)): Path<(String, String)>,
+    State(state): State<AppState>,
+    caller: Caller,
+) -> impl IntoResponse {
+    let a = match authorize(&state, &id, &caller, Role::Viewer).await {
+        Ok(room) => room,
+        Err(resp) => return resp,
+    };
+    let b = match authorize(&state, &other, &caller, Role::Viewer).await {
+        Ok(room) => room,
+        Err(resp) => return resp,
+    };
+    let old = a.doc.lock().await.get_text();
+    let new = b.doc.lock().await.get_text();
+    Json(diff::hunks(&old, &new)).into_response()
+}
+
+async fn list_tags(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    caller: Caller,
+) -> impl IntoResponse {
+    let room = match authorize(&state, &id, &caller, Role::Viewer).await {
+        Ok(room) => room,
+        Err(resp) => return resp,
+    };
+    let tags = room.meta.lock().await.tags.clone();
+    Json(tags).into_response()
+}
+
+async fn create_tag(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    caller: Caller,
+    Json(body): Json<TagBody>,
+) -> impl IntoResponse {
+    let room = match authorize(&state, &id, &caller, Role::Editor).await {
+        Ok(room) => room,
+        Err(resp) => return resp,
+    };
+    let name = body.name.trim().to_string();
+    if name.is_empty() {
+        return (StatusCode::BAD_REQUEST, "tag needs a name").into_response();
+    }
+    let heads = {
+        let mut doc = room.doc.lock().await;
+        let heads = match body.heads {
+            Some(heads) if doc.text_at(&heads).is_none() => {
+                return (StatusCode::BAD_REQUEST, "unknown heads").into_response();
+            }
+            Some(heads) => heads,
+            None        => doc.heads(),
+        };
+        // legacy .md docs get fresh hashes on every load, so pin the history to disk first
+        room.persist(&id, doc.get_text(), doc.save());
+        heads
+    };
+    let tag = Tag {
+        name,
+        heads,
+        created: now_secs(),
+    };
+    let mut meta = room.meta.lock().await;
+    if meta.tags.iter().any(|t| t.name == tag.name) {
+        return (StatusCode::CONFLICT, "a tag with that name already exists").into_response();
+    }
+    meta.tags.push(tag.clone());
+    meta.persist(&id);
+    Json(tag).into_response()
+}
+
+async fn delete_tag(
+    Path((id, name)): Path<(String, String)>,
+    State(state): State<AppState>,
+    caller: Caller,
+) -> impl IntoResponse {
+    let room = match authorize(&state, &id, &caller, Role::Editor).await {
+        Ok(room) => room,
+        Err(resp) => return resp,
+    };
+    let mut meta = room.meta.lock().await;
+    let before = meta.tags.len();
+    meta.tags.retain(|t| t.name != name);
+    if meta.tags.len() == before {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    meta.persist(&id);
+    StatusCode::NO_CONTENT.into_response()
+}
+
+// text at a tag, ?download=true sends it as a .md file instead
+async fn tag_text(
+    Path((id, name)): Path<(String, String)>,
+    Query(q): Query<ExportQuery>,
+    State(state): State<AppState>,
+    caller: Caller,
+) -> impl IntoResponse {
+    let room = match authorize(&state, &id, &caller, Role::Viewer).await {
+        Ok(room) => room,
+        Err(resp) => return resp,
+    };
+    let heads = room.meta.lock().await.tags.iter().find(|t| t.name == name).map(|t| t.heads.clone());
+    let Some(heads) = heads else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let Some(text) = room.doc.lock().await.text_at(&heads) else {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+    if !q.download {
+        return text.into_response();
+    }
+    let filename = safe_file_name(&format!("{id}-{name}.md"));
+    (
+        [
+            (header::CONTENT_TYPE, "text/markdown; charset=utf-8".to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{filename}\"")),
+        ],
+        text,
+    ).into_response()
+}
+
+async fn blame(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    caller: Caller,
+) -> impl IntoResponse {
+    let room = match authorize(&state, &id, &caller, Role::Viewer).await {
+        Ok(room) => room,
+        Err(resp) => return resp,
+    };
+    let runs = room.doc.lock().await.blame();
+    let meta = room.meta.lock().await;
+    let spans: Vec<BlameSpan> = runs
+        .into_iter()
+        .map(|run| BlameSpan {
+            name:  meta.authors.get(&run.actor).cloned().unwrap_or_default(),
+            text:  run.text,
+            actor: run.actor,
+            time:  run.time,
+        })
+        .collect();
+    Json(spans).into_response()
+}
+
+// the token behind the doc's read only link, made on first request
+async fn view_token(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    caller: Caller,
+) -> impl IntoResponse {
+    let room = match authorize(&state, &id, &caller, Role::Editor).await {
+        Ok(room) => room,
+        Err(resp) => return resp,
+    };
+    let token = state.view_token(&id, &room).await;
+    Json(serde_json::json!({ "token": token })).into_response()
+}
+
+async fn get_sharing(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    caller: Caller,
+) -> impl IntoResponse {
+    let Some(room) = state.get_room(&id) else {
+        // nothing here yet, whoever opens it first gets a fresh doc they can edit
+        return Json(Sharing { my_role: Some(Role::Editor), link_role: Some(Role::Editor), ..Sharing::default() }).into_response();
+    };
+    let meta = room.meta.lock().await;
+    let my_role = caller.role(&id, &meta);
+    if my_role.is_none() {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+    // the editor asks this first, so this is how it knows to show the password screen
+    if caller.needs_password(&id, &meta, &state.passes) {
+        return (StatusCode::UNAUTHORIZED, "password required").into_response();
+    }
+    if my_role != Some(Role::Owner) {
+        return Json(Sharing { my_role, ..Sharing::default() }).into_response();
+    }
+    let grants = meta
+        .acl
+        .iter()
+        .filter_map(|(user_id, role)| {
+            Some(Grant { username: state.accounts.username_for(user_id)?, role: *role })
+        })
+        .collect();
+    Json(Sharing {
+        my_role,
+        owner: meta.owner.as_deref().and_then(|o| state.accounts.username_for(o)),
+        link_role: meta.link_role,
+        link_floor: meta.link_floor,
+        grants,
+        has_password: meta.password_hash.is_some(),
+    }).into_response()
+}
+
+async fn set_sharing(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    caller: Caller,
+    Json(body): Json<SharingBody>,
+) -> impl IntoResponse {
+    let room = match authorize(&state, &id, &caller, Role::Owner).await {
+        Ok(room) => room,
+        Err(resp) => return resp,
+    };
+    let mut acl = std::collections::BTreeMap::new();
+    for grant in &body.grants {
+        let Some(user) = state.accounts.find_by_username(&grant.username) else {
+            return (StatusCode::BAD_REQUEST, format!("no user called {}", grant.username)).into_response();
+        };
+        // there's only ever one owner, handing that over isn't done through the acl
+        if grant.role == Role::Owner {
+            return (StatusCode::BAD_REQUEST, "can't grant owner").into_response();
+        }
+        acl.insert(user.id, grant.role);
+    }
+    if body.link_role == Some(Role::Owner) {
+        return (StatusCode::BAD_REQUEST, "can't grant owner").into_response();
+    }
+    let mut meta = room.meta.lock().await;
+    if let Err(message) = meta.set_link_role(body.link_role) {
+        return (StatusCode::BAD_REQUEST, message).into_response();
+    }
+    meta.acl = acl;
+    meta.persist(&id);
+    StatusCode::NO_CONTENT.into_response()
+}
+
+// docs from before accounts have no owner, a signed in editor can take them over but not
+// lock out anyone who already had the link. a person does this, not an api token
+async fn claim(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    caller: Caller,
+) -> impl IntoResponse {
+    let Some(user) = caller.user.clone().filter(|_| caller.token.is_none()) else {
+        return (StatusCode::UNAUTHORIZED, "sign in to claim this doc").into_response();
+    };
+    let room = match authorize(&state, &id, &caller, Role::Editor).await {
+        Ok(room) => room,
+        Err(resp) => return resp,
+    };
+    let mut meta = room.meta.lock().await;
+    if let Err(message) = meta.claim(&user.id) {
+        return (StatusCode::CONFLICT, message).into_response();
+    }
+    meta.persist(&id);
+    StatusCode::NO_CONTENT.into_response()
+}
@@ -0,0 +1,270 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{delete, get},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use reality::protocol::{NewToken, Role, TokenInfo, TokenScope};
+use crate::api::authorize;
+use crate::auth::Caller;
+use crate::state::{now_secs, AppState};
+
+const TOKENS_PATH: &str = "docs/tokens.json";
+// so a token pasted into a ci config is recognisable as ours
+const TOKEN_PREFIX: &str = "rt_";
+
+#[derive(Serialize, Deserialize, Clone)]
+struct StoredToken {
+    id:      String,
+    name:    String,
+    // sha256 of the secret, tokens are long and random so a slow hash buys nothing
+    hash:    String,
+    scope:   TokenScope,
+    // who made it, and who a user token acts as
+    user_id: String,
+    doc_id:  Option<String>,
+    created: i64,
+}
+
+impl StoredToken {
+    fn info(&self) -> TokenInfo {
+        TokenInfo {
+            id:      self.id.clone(),
+            name:    self.name.clone(),
+            scope:   self.scope,
+            doc_id:  self.doc_id.clone(),
+            created: self.created,
+        }
+    }
+}
+
+// what a request gets from presenting a token
+#[derive(Clone, Debug)]
+pub struct TokenGrant {
+    pub id:      String,
+    pub scope:   TokenScope,
+    pub user_id: String,
+    pub doc_id:  Option<String>,
+}
+
+// api tokens live in docs/tokens.json, keyed by hash so lookups never need the secret
+#[derive(Clone, Default)]
+pub struct ApiTokens {
+    tokens:  Arc<RwLock<HashMap<String, StoredToken>>>,
+    // held from taking the snapshot to finishing the write, so an older snapshot can't land
+    // on top of a newer one and bring back a revoked token
+    writing: Arc<Mutex<()>>,
+}
+
+fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+impl ApiTokens {
+    pub fn load() -> Self {
+        let tokens: Vec<StoredToken> = match std::fs::read(TOKENS_PATH) {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+                eprintln!("Failed to parse {TOKENS_PATH}: {e}");
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+        let tokens = tokens.into_iter().map(|t| (t.hash.clone(), t)).collect();
+        Self { tokens: Arc::new(RwLock::new(tokens)), writing: Arc::default() }
+    }
+
+    // written to the side and renamed over, so a crash mid write never leaves half a file
+    fn persist(&self) {
+        let _writing = self.writing.lock().unwrap();
+        let tokens: Vec<StoredToken> = self.tokens.read().unwrap().values().cloned().collect();
+        let json = serde_json::to_vec_pretty(&tokens).unwrap();
+        if let Err(e) = std::fs::create_dir_all("docs") {
+            eprintln!("create_dir_all failed: {e}");
+            return;
+        }
+        let tmp = format!("{TOKENS_PATH}.tmp");
+        if let Err(e) = std::fs::write(&tmp, &json).and_then(|_| std::fs::rename(&tmp, TOKENS_PATH)) {
+            eprintln!("write {TOKENS_PATH} failed: {e}");
+        }
+    }
+
+    fn create(&self, user_id: &str, doc_id: Option<&str>, name: &str, scope: TokenScope) -> NewToken {
+        let token = format!("{TOKEN_PREFIX}{}{}", uuid::Uuid::new_v4().simple(), uuid::Uuid::new_v4().simple());
+        let stored = StoredToken {
+            id:      uuid::Uuid::new_v4().simple().to_string(),
+            name:    name.to_string(),
+            hash:    hash_token(&token),
+            scope,
+            user_id: user_id.to_string(),
+            doc_id:  doc_id.map(str::to_string),
+            created: now_secs(),
+        };
+        let info = stored.info();
+        self.tokens.write().unwrap().insert(stored.hash.clone(), stored);
+        self.persist();
+        NewToken { token, info }
+    }
+
+    fn list(&self, keep: impl Fn(&StoredToken) -> bool) -> Vec<TokenInfo> {
+        let mut infos: Vec<TokenInfo> = self.tokens.read().unwrap().values().filter(|t| keep(t)).map(StoredToken::info).collect();
+        infos.sort_by_key(|t| t.created);
+        infos
+    }
+
+    // false if there was no such token or it isn't one `allowed` lets you touch
+    fn revoke(&self, id: &str, allowed: impl Fn(&StoredToken) -> bool) -> bool {
+        let removed = {
+            let mut tokens = self.tokens.write().unwrap();
+            let before = tokens.len();
+            tokens.retain(|_, t| t.id != id || !allowed(t));
+            tokens.len() != before
+        };
+        if removed {
+            self.persist();
+        }
+        removed
+    }
+
+    pub fn lookup(&self, token: &str) -> Option<TokenGrant> {
+        let tokens = self.tokens.read().unwrap();
+        let t = tokens.get(&hash_token(token))?;
+        Some(TokenGrant { id: t.id.clone(), scope: t.scope, user_id: t.user_id.clone(), doc_id: t.doc_id.clone() })
+    }
+
+    // sockets hold on to their grant, so they ask this to notice a revoke
+    pub fn is_live(&self, id: &str) -> bool {
+        self.tokens.read().unwrap().values().any(|t| t.id == id)
+    }
+}
+
+pub fn tokens_router(state: AppState) -> Router {
+    Router::new()
+        .route("/api/tokens", get(my_tokens).post(create_my_token))
+        .route("/api/tokens/:tid", delete(revoke_my_token))
+        .route("/api/doc/:id/tokens", get(doc_tokens).post(create_doc_token))
+        .route("/api/doc/:id/tokens/:tid", delete(revoke_doc_token))
+        .with_state(state)
+}
+
+#[derive(Deserialize)]
+struct TokenBody {
+    name:  String,
+    scope: TokenScope,
+}
+
+// tokens are managed from the browser, a token can't be used to mint more tokens
+fn signed_in(caller: &Caller) -> Result<String, (StatusCode, &'static str)> {
+    match (&caller.user, &caller.token) {
+        (Some(user), None) => Ok(user.id.clone()),
+        (_, Some(_))       => Err((StatusCode::FORBIDDEN, "api tokens can't manage tokens")),
+        (None, None)       => Err((StatusCode::UNAUTHORIZED, "sign in first")),
+    }
+}
+
+fn token_name(name: &str) -> Result<&str, (StatusCode, &'static str)> {
+    let name = name.trim();
+    if name.is_empty() || name.len() > 64 {
+        return Err((StatusCode::BAD_REQUEST, "token name must be 1-64 characters"));
+    }
+    Ok(name)
+}
+
+async fn my_tokens(
+    State(state): State<AppState>,
+    caller: Caller,
+) -> impl IntoResponse {
+    let user_id = match signed_in(&caller) {
+        Ok(id) => id,
+        Err(e) => return e.into_response(),
+    };
+    Json(state.tokens.list(|t| t.user_id == user_id && t.doc_id.is_none())).into_response()
+}
+
+async fn create_my_token(
+    State(state): State<AppState>,
+    caller: Caller,
+    Json(body): Json<TokenBody>,
+) -> impl IntoResponse {
+    let user_id = match signed_in(&caller) {
+        Ok(id) => id,
+        Err(e) => return e.into_response(),
+    };
+    let name = match token_name(&body.name) {
+        Ok(name) => name,
+        Err(e) => return e.into_response(),
+    };
+    Json(state.tokens.create(&user_id, None, name, body.scope)).into_response()
+}
+
+async fn revoke_my_token(
+    Path(tid): Path<String>,
+    State(state): State<AppState>,
+    caller: Caller,
+) -> impl IntoResponse {
+    let user_id = match signed_in(&caller) {
+        Ok(id) => id,
+        Err(e) => return e.into_response(),
+    };
+    if state.tokens.revoke(&tid, |t| t.user_id == user_id && t.doc_id.is_none()) {
+        StatusCode::NO_CONTENT.into_response()
+    } else {
+        StatusCode::NOT_FOUND.into_response()
+    }
+}
+
+async fn doc_tokens(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    caller: Caller,
+) -> impl IntoResponse {
+    if let Err(e) = signed_in(&caller) {
+        return e.into_response();
+    }
+    if let Err(resp) = authorize(&state, &id, &caller, Role::Owner).await {
+        return resp;
+    }
+    Json(state.tokens.list(|t| t.doc_id.as_deref() == Some(id.as_str()))).into_response()
+}
+
+async fn create_doc_token(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    caller: Caller,
+    Json(body): Json<TokenBody>,
+) -> impl IntoResponse {
+    let user_id = match signed_in(&caller) {
+        Ok(id) => id,
+        Err(e) => return e.into_response(),
+    };
+    if let Err(resp) = authorize(&state, &id, &caller, Role::Owner).await {
+        return resp;
+    }
+    let name = match token_name(&body.name) {
+        Ok(name) => name,
+        Err(e) => return e.into_response(),
+    };
+    Json(state.tokens.create(&user_id, Some(&id), name, body.scope)).into_response()
+}
+
+async fn revoke_doc_token(
+    Path((id, tid)): Path<(String, String)>,
+    State(state): State<AppState>,
+    caller: Caller,
+) -> impl IntoResponse {
+    if let Err(e) = signed_in(&caller) {
+        return e.into_response();
+    }
+    if let Err(resp) = authorize(&state, &id, &caller, Role::Owner).await {
+        return resp;
+    }
+    if state.tokens.revoke(&tid, |t| t.doc_id.as_deref() == Some(id.as_str())) {
+        StatusCode::NO_CONTENT.into_response()
+    } else {
+        StatusCode::NOT_FOUND.into_response()
+    }
+}
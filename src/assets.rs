@@ -0,0 +1,209 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{rejection::BytesRejection, DefaultBodyLimit, Path, Request, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use sha2::{Digest, Sha256};
+use tower_http::services::{ServeDir, ServeFile};
+use reality::protocol::{Asset, Role};
+use crate::api::authorize;
+use crate::auth::Caller;
+use crate::state::AppState;
+
+// images pasted or dropped into the editor. files are named by the sha256 of their bytes so the
+// same screenshot is only kept once, and a name can never point at different bytes later.
+// they're under docs/ so backing that up keeps them too
+const ASSETS_DIR: &str = "docs/assets";
+
+// the app's own bundled files are under /assets/ as well, anything that isn't a hash goes to them
+pub fn assets_router(state: AppState, files: ServeDir<ServeFile>) -> Router {
+    Router::new()
+        .route(
+            "/api/doc/:id/assets",
+            post(upload).layer(DefaultBodyLimit::max(state.limits.max_asset_bytes)),
+        )
+        .route("/assets/:hash", get(move |Path(hash): Path<String>, req: Request| serve(hash, req, files.clone())))
+        .with_state(state)
+}
+
+// what the bytes actually are, whatever the browser said. svg isn't on the list because
+// it can carry script, and these are served from our own origin
+pub fn image_type(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if bytes.starts_with(&[0xff, 0xd8, 0xff]) {
+        Some("image/jpeg")
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else {
+        None
+    }
+}
+
+pub fn asset_path(hash: &str) -> String {
+    format!("{ASSETS_DIR}/{hash}")
+}
+
+fn is_hash(s: &str) -> bool {
+    s.len() == 64 && s.bytes().all(|b| b.is_ascii_hexdigit() && !b.is_ascii_uppercase())
+}
+
+// the bytes as dir/<sha256>, left alone if they're already there. written under another
+// name first so a half written file is never served
+async fn store(dir: &str, bytes: &[u8]) -> std::io::Result<String> {
+    let hash = format!("{:x}", Sha256::digest(bytes));
+    let path = format!("{dir}/{hash}");
+    if tokio::fs::try_exists(&path).await.unwrap_or(false) {
+        return Ok(hash);
+    }
+    tokio::fs::create_dir_all(dir).await?;
+    let tmp = format!("{path}.{}.tmp", uuid::Uuid::new_v4().simple());
+    tokio::fs::write(&tmp, bytes).await?;
+    if let Err(e) = tokio::fs::rename(&tmp, &path).await {
+        let _ = tokio::fs::remove_file(&tmp).await;
+        return Err(e);
+    }
+    Ok(hash)
+}
+
+// the body is the file itself, editors only since it ends up in their doc
+async fn upload(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    caller: Caller,
+    body: Result<Bytes, BytesRejection>,
+) -> impl IntoResponse {
+    if let Err(resp) = authorize(&state, &id, &caller, Role::Editor).await {
+        return resp;
+    }
+    let bytes = match body {
+        Ok(bytes) => bytes,
+        Err(e) if e.status() == StatusCode::PAYLOAD_TOO_LARGE => {
+            return (StatusCode::PAYLOAD_TOO_LARGE, state.limits.asset_too_big_message()).into_response();
+        }
+        Err(e) => return e.into_response(),
+    };
+    let Some(content_type) = image_type(&bytes) else {
+        return (StatusCode::UNSUPPORTED_MEDIA_TYPE, "Only PNG, JPEG, GIF and WebP images can be uploaded.").into_response();
+    };
+    let hash = match store(ASSETS_DIR, &bytes).await {
+        Ok(hash) => hash,
+        Err(e) => {
+            eprintln!("storing an asset for {id} failed: {e}");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    Json(Asset {
+        url: format!("/assets/{hash}"),
+        hash,
+        content_type: content_type.to_string(),
+        size: bytes.len(),
+    }).into_response()
+}
+
+// anyone with the hash can fetch it, the same as anyone with a link to a published page
+// can read the page the image is in. the bytes behind a hash never change so it caches forever
+async fn serve(hash: String, req: Request, mut files: ServeDir<ServeFile>) -> Response {
+    if !is_hash(&hash) {
+        return match files.try_call(req).await {
+            Ok(resp) => resp.map(Body::new),
+            Err(e) => {
+                eprintln!("serving {hash} failed: {e}");
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        };
+    }
+    let headers = req.headers();
+    let etag = format!("\"{hash}\"");
+    let cache = [
+        (header::ETAG, etag.clone()),
+        (header::CACHE_CONTROL, "public, max-age=31536000, immutable".to_string()),
+        (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+    ];
+    if headers.get(header::IF_NONE_MATCH).and_then(|v| v.to_str().ok()) == Some(etag.as_str()) {
+        return (StatusCode::NOT_MODIFIED, cache).into_response();
+    }
+    let Ok(bytes) = tokio::fs::read(asset_path(&hash)).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let content_type = image_type(&bytes).unwrap_or("application/octet-stream");
+    (cache, [(header::CONTENT_TYPE, content_type)], bytes).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn type_comes_from_the_bytes() {
+        assert_eq!(image_type(b"\x89PNG\r\n\x1a\n rest"), Some("image/png"));
+        assert_eq!(image_type(&[0xff, 0xd8, 0xff, 0xe0]), Some("image/jpeg"));
+        assert_eq!(image_type(b"GIF89a..."), Some("image/gif"));
+        assert_eq!(image_type(b"RIFF\0\0\0\0WEBPVP8 "), Some("image/webp"));
+        assert_eq!(image_type(b"RIFF\0\0\0\0WAVEfmt "), None);
+        assert_eq!(image_type(b"RIFF"), None);
+        // svg can carry script, whatever it says it is
+        assert_eq!(image_type(b"<svg xmlns=\"http://www.w3.org/2000/svg\" onload=\"alert(1)\"/>"), None);
+        assert_eq!(image_type(b"<?xml version=\"1.0\"?><svg/>"), None);
+        assert_eq!(image_type(b""), None);
+    }
+
+    #[test]
+    fn only_hashes_name_assets() {
+        assert!(is_hash(&"ab".repeat(32)));
+        assert!(!is_hash(&"AB".repeat(32)));
+        assert!(!is_hash(&"ab".repeat(31)));
+        assert!(!is_hash("../../docs/users.json"));
+    }
+
+    #[tokio::test]
+    async fn bundled_assets_still_get_served() {
+        use tower::ServiceExt;
+        let dir = std::env::temp_dir().join(format!("reality-public-{}", uuid::Uuid::new_v4().simple()));
+        std::fs::create_dir_all(dir.join("assets")).unwrap();
+        std::fs::write(dir.join("assets/main.css"), "body{}").unwrap();
+        std::fs::write(dir.join("index.html"), "<html></html>").unwrap();
+        let files  = ServeDir::new(&dir).fallback(ServeFile::new(dir.join("index.html")));
+        let router = assets_router(AppState::in_memory(), files);
+
+        let get = |uri: &str| {
+            let req = axum::http::Request::builder().uri(uri).body(Body::empty()).unwrap();
+            router.clone().oneshot(req)
+        };
+        let resp = get("/assets/main.css").await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()[header::CONTENT_TYPE], "text/css");
+        let body = axum::body::to_bytes(resp.into_body(), 1024).await.unwrap();
+        assert_eq!(&body[..], b"body{}");
+        // an upload that isn't there is a plain 404, not the app's index page
+        assert_eq!(get(&format!("/assets/{}", "ab".repeat(32))).await.unwrap().status(), StatusCode::NOT_FOUND);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn stored_under_their_hash_once() {
+        let dir = std::env::temp_dir().join(format!("reality-assets-{}", uuid::Uuid::new_v4().simple()));
+        let dir = dir.to_str().unwrap();
+        let png = b"\x89PNG\r\n\x1a\nsome pixels";
+
+        let hash = store(dir, png).await.unwrap();
+        assert_eq!(hash, format!("{:x}", Sha256::digest(png)));
+        assert_eq!(store(dir, png).await.unwrap(), hash);
+        let other = store(dir, b"GIF89a other pixels").await.unwrap();
+        assert_ne!(other, hash);
+
+        let mut names: Vec<String> = std::fs::read_dir(dir).unwrap().map(|e| e.unwrap().file_name().into_string().unwrap()).collect();
+        names.sort();
+        let mut expected = vec![hash.clone(), other];
+        expected.sort();
+        // nothing half written is left behind
+        assert_eq!(names, expected);
+        assert_eq!(std::fs::read(format!("{dir}/{hash}")).unwrap(), png);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
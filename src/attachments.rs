@@ -0,0 +1,519 @@
+use std::time::Duration;
+use axum::{
+    body::Bytes,
+    extract::{rejection::BytesRejection, DefaultBodyLimit, Path, Query, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    routing::{delete, get},
+    Json, Router,
+};
+use serde::Deserialize;
+use reality::doc::Doc;
+use reality::protocol::{safe_file_name, Attachment, Role};
+use crate::api::authorize;
+use crate::assets::{asset_path, image_type};
+use crate::auth::Caller;
+use crate::meta::DocMeta;
+use crate::state::{now_secs, AppState};
+
+// files that belong to one doc (pdfs, logs, csvs), kept next to it in docs/{id}.files/ so
+// copying docs/ backs them up too. unlike images they're private to the doc
+pub fn attachments_router(state: AppState) -> Router {
+    Router::new()
+        .route(
+            "/api/doc/:id/attachments",
+            get(list).post(upload).layer(DefaultBodyLimit::max(state.limits.max_attachment_bytes)),
+        )
+        .route("/api/doc/:id/attachments/:file", delete(remove))
+        .route("/api/doc/:id/attachments/:file/:name", get(download))
+        .route("/api/doc/:id/export", get(export))
+        .with_state(state)
+}
+
+// how often unlinked attachments are looked for, and how long one stays unlinked before it goes.
+// the wait is so cutting a link and pasting it back, or undoing, doesn't lose the file
+const GC_EVERY: Duration = Duration::from_secs(10 * 60);
+const KEEP_UNLINKED_SECS: i64 = 24 * 60 * 60;
+// files in a doc's folder that its meta doesn't list, left by a crash mid upload. given a while
+// first so an upload that's between writing its file and saving the meta isn't caught
+const STRAY_AFTER: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Deserialize)]
+struct UploadQuery {
+    name: String,
+}
+
+fn file_path(id: &str, file: &str) -> String {
+    format!("docs/{id}.files/{file}")
+}
+
+// ids are uuids we made, anything else never reaches the disk
+fn is_file_id(s: &str) -> bool {
+    s.len() == 32 && s.bytes().all(|b| b.is_ascii_hexdigit() && !b.is_ascii_uppercase())
+}
+
+async fn list(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    caller: Caller,
+) -> impl IntoResponse {
+    let room = match authorize(&state, &id, &caller, Role::Viewer).await {
+        Ok(room) => room,
+        Err(resp) => return resp,
+    };
+    let attachments = room.meta.lock().await.attachments.clone();
+    Json(attachments).into_response()
+}
+
+// the body is the file, ?name= is what it was called on the uploader's machine
+async fn upload(
+    Path(id): Path<String>,
+    Query(q): Query<UploadQuery>,
+    State(state): State<AppState>,
+    caller: Caller,
+    body: Result<Bytes, BytesRejection>,
+) -> impl IntoResponse {
+    let room = match authorize(&state, &id, &caller, Role::Editor).await {
+        Ok(room) => room,
+        Err(resp) => return resp,
+    };
+    let bytes = match body {
+        Ok(bytes) => bytes,
+        Err(e) if e.status() == StatusCode::PAYLOAD_TOO_LARGE => {
+            return (StatusCode::PAYLOAD_TOO_LARGE, state.limits.attachment_too_big_message()).into_response();
+        }
+        Err(e) => return e.into_response(),
+    };
+    let name: String = q.name.chars().filter(|c| !c.is_control()).take(200).collect();
+    let name = name.trim();
+    if name.is_empty() {
+        return (StatusCode::BAD_REQUEST, "attachment needs a name").into_response();
+    }
+
+    let file = uuid::Uuid::new_v4().simple().to_string();
+    let path = file_path(&id, &file);
+    if let Err(e) = tokio::fs::create_dir_all(format!("docs/{id}.files")).await {
+        eprintln!("create_dir_all for {path} failed: {e}");
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+    // written under another name first so a half written file is never downloaded
+    let tmp = format!("{path}.tmp");
+    let written = match tokio::fs::write(&tmp, &bytes).await {
+        Ok(()) => tokio::fs::rename(&tmp, &path).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = written {
+        eprintln!("write {path} failed: {e}");
+        let _ = tokio::fs::remove_file(&tmp).await;
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+    let attachment = Attachment {
+        id:      file,
+        name:    name.to_string(),
+        size:    bytes.len(),
+        created: now_secs(),
+        unreferenced_since: None,
+    };
+    let mut meta = room.meta.lock().await;
+    meta.attachments.push(attachment.clone());
+    meta.persist(&id);
+    Json(attachment).into_response()
+}
+
+async fn remove(
+    Path((id, file)): Path<(String, String)>,
+    State(state): State<AppState>,
+    caller: Caller,
+) -> impl IntoResponse {
+    let room = match authorize(&state, &id, &caller, Role::Editor).await {
+        Ok(room) => room,
+        Err(resp) => return resp,
+    };
+    let mut meta = room.meta.lock().await;
+    let before = meta.attachments.len();
+    meta.attachments.retain(|a| a.id != file);
+    if meta.attachments.len() == before {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    meta.persist(&id);
+    drop(meta);
+    if let Err(e) = tokio::fs::remove_file(file_path(&id, &file)).await {
+        eprintln!("remove attachment {file} of {id} failed: {e}");
+    }
+    StatusCode::NO_CONTENT.into_response()
+}
+
+// always a download, whatever is in the file never runs as a page on our origin
+async fn download(
+    Path((id, file, _name)): Path<(String, String, String)>,
+    State(state): State<AppState>,
+    caller: Caller,
+) -> impl IntoResponse {
+    let room = match authorize(&state, &id, &caller, Role::Viewer).await {
+        Ok(room) => room,
+        Err(resp) => return resp,
+    };
+    let name = room.meta.lock().await.attachments.iter().find(|a| a.id == file).map(|a| a.name.clone());
+    let Some(name) = name.filter(|_| is_file_id(&file)) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let Ok(bytes) = tokio::fs::read(file_path(&id, &file)).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    (
+        [
+            (header::CONTENT_TYPE, "application/octet-stream".to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", safe_file_name(&name))),
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+        ],
+        bytes,
+    ).into_response()
+}
+
+fn tar_entry(tar: &mut tar::Builder<Vec<u8>>, path: &str, bytes: &[u8]) -> std::io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(bytes.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(now_secs().max(0) as u64);
+    tar.append_data(&mut header, path, bytes)
+}
+
+// every link starting with prefix, and whatever path follows it, pointed at `to` instead.
+// the name after an attachment's id is only decoration, so a hand typed one still counts,
+// and so does a link someone copied with this server's address in front
+fn relink(text: &str, prefix: &str, to: &str) -> String {
+    let mut out  = String::new();
+    let mut rest = text;
+    while let Some(at) = rest.find(prefix) {
+        let head  = &rest[..at];
+        let start = head.char_indices().rev()
+            .find(|(_, c)| c.is_whitespace() || "(<[\"'".contains(*c))
+            .map_or(0, |(i, c)| i + c.len_utf8());
+        let absolute = head[start..].starts_with("http://") || head[start..].starts_with("https://");
+        out.push_str(&rest[..if absolute { start } else { at }]);
+        out.push_str(to);
+        rest = &rest[at + prefix.len()..];
+        if rest.starts_with('/') {
+            let end = rest.find(|c: char| c.is_whitespace() || ")]>\"'".contains(c)).unwrap_or(rest.len());
+            rest = &rest[end..];
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+// /assets/<hash> links in the text, each once
+fn linked_assets(text: &str) -> Vec<String> {
+    let mut hashes: Vec<String> = Vec::new();
+    for (at, _) in text.match_indices("/assets/") {
+        let hash: String = text[at + "/assets/".len()..].chars().take_while(char::is_ascii_hexdigit).collect();
+        if hash.len() == 64 && !hashes.contains(&hash) {
+            hashes.push(hash);
+        }
+    }
+    hashes
+}
+
+// the doc as a .tar: the markdown with its links pointing inside the archive, every attachment
+// and linked image, and the .am with the full history
+async fn export(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    caller: Caller,
+) -> impl IntoResponse {
+    let room = match authorize(&state, &id, &caller, Role::Viewer).await {
+        Ok(room) => room,
+        Err(resp) => return resp,
+    };
+    let (mut text, history) = {
+        let mut doc = room.doc.lock().await;
+        (doc.get_text(), doc.save())
+    };
+    let attachments = room.meta.lock().await.attachments.clone();
+    let mut files: Vec<(String, Vec<u8>)> = Vec::new();
+
+    for attachment in &attachments {
+        let Ok(bytes) = tokio::fs::read(file_path(&id, &attachment.id)).await else { continue };
+        // two files with the same name both make it in
+        let mut path = format!("attachments/{}", safe_file_name(&attachment.name));
+        if files.iter().any(|(p, _)| *p == path) {
+            path = format!("attachments/{}-{}", &attachment.id[..8], safe_file_name(&attachment.name));
+        }
+        text = relink(&text, &format!("/api/doc/{id}/attachments/{}", attachment.id), &path);
+        files.push((path, bytes));
+    }
+    for hash in linked_assets(&text) {
+        let Ok(bytes) = tokio::fs::read(asset_path(&hash)).await else { continue };
+        let ext  = image_type(&bytes).and_then(|t| t.strip_prefix("image/")).unwrap_or("bin");
+        let path = format!("assets/{hash}.{ext}");
+        text = relink(&text, &format!("/assets/{hash}"), &path);
+        files.push((path, bytes));
+    }
+
+    let name = safe_file_name(&id);
+    let mut tar = tar::Builder::new(Vec::new());
+    let built = tar_entry(&mut tar, &format!("{name}.md"), text.as_bytes())
+        .and_then(|_| tar_entry(&mut tar, &format!("{name}.am"), &history))
+        .and_then(|_| files.iter().try_for_each(|(path, bytes)| tar_entry(&mut tar, path, bytes)))
+        .and_then(|_| tar.into_inner());
+    let archive = match built {
+        Ok(archive) => archive,
+        Err(e) => {
+            eprintln!("export of {id} failed: {e}");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    (
+        [
+            (header::CONTENT_TYPE, "application/x-tar".to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{name}.tar\"")),
+        ],
+        archive,
+    ).into_response()
+}
+
+// the id on its own somewhere in the text, not as part of some longer run of hex
+fn mentions(text: &str, id: &str) -> bool {
+    text.match_indices(id).any(|(at, _)| {
+        let before = text[..at].bytes().next_back();
+        let after  = text[at + id.len()..].bytes().next();
+        !before.is_some_and(|b| b.is_ascii_hexdigit()) && !after.is_some_and(|b| b.is_ascii_hexdigit())
+    })
+}
+
+// marks attachments the text stopped linking to, unmarks ones that got linked again,
+// and hands back the ones that have been unlinked for long enough
+fn sweep(attachments: &mut Vec<Attachment>, text: &str, now: i64) -> (bool, Vec<Attachment>) {
+    let mut changed = false;
+    for attachment in attachments.iter_mut() {
+        let linked = mentions(text, &attachment.id);
+        match attachment.unreferenced_since {
+            Some(_) if linked => { attachment.unreferenced_since = None; changed = true; }
+            None if !linked   => { attachment.unreferenced_since = Some(now); changed = true; }
+            _                 => {}
+        }
+    }
+    let (gone, kept) = attachments
+        .drain(..)
+        .partition(|a| a.unreferenced_since.is_some_and(|since| now - since >= KEEP_UNLINKED_SECS));
+    *attachments = kept;
+    (changed || !gone.is_empty(), gone)
+}
+
+async fn remove_gone(dir: &str, id: &str, gone: Vec<Attachment>) {
+    for attachment in gone {
+        println!("Removing unlinked attachment {} ({}) from {id}", attachment.name, attachment.id);
+        if let Err(e) = tokio::fs::remove_file(format!("{dir}/{id}.files/{}", attachment.id)).await {
+            eprintln!("remove attachment {} of {id} failed: {e}", attachment.id);
+        }
+    }
+}
+
+async fn remove_strays(dir: &str, id: &str, attachments: &[Attachment]) {
+    let Ok(mut entries) = tokio::fs::read_dir(format!("{dir}/{id}.files")).await else { return };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let name = entry.file_name();
+        if attachments.iter().any(|a| name.to_str() == Some(a.id.as_str())) {
+            continue;
+        }
+        let old = entry.metadata().await
+            .and_then(|m| m.modified())
+            .is_ok_and(|at| at.elapsed().is_ok_and(|age| age >= STRAY_AFTER));
+        if !old {
+            continue;
+        }
+        println!("Removing stray file {} from {id}", name.to_string_lossy());
+        if let Err(e) = tokio::fs::remove_file(entry.path()).await {
+            eprintln!("remove stray file {} of {id} failed: {e}", name.to_string_lossy());
+        }
+    }
+}
+
+// the text of a doc that isn't loaded, None when it's there but can't be read, since
+// treating that as empty would throw away every attachment it has
+async fn text_on_disk(dir: &str, id: &str) -> Option<String> {
+    match tokio::fs::read(format!("{dir}/{id}.am")).await {
+        Ok(bytes) => return Doc::load_from_bytes(&bytes).map(|doc| doc.get_text()),
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return None,
+        Err(_) => {}
+    }
+    match tokio::fs::read_to_string(format!("{dir}/{id}.md")).await {
+        Ok(text) => Some(text),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Some(String::new()),
+        Err(_) => None,
+    }
+}
+
+async fn collect(state: &AppState, dir: &str, now: i64) {
+    let rooms: Vec<_> = state.rooms.iter().map(|r| (r.key().clone(), r.value().clone())).collect();
+    for (id, room) in rooms {
+        let attachments = room.meta.lock().await.attachments.clone();
+        if attachments.is_empty() {
+            remove_strays(dir, &id, &attachments).await;
+            continue;
+        }
+        let text = room.doc.lock().await.get_text();
+        let mut meta = room.meta.lock().await;
+        let (changed, gone) = sweep(&mut meta.attachments, &text, now);
+        if changed {
+            meta.persist(&id);
+        }
+        let kept = meta.attachments.clone();
+        drop(meta);
+        remove_gone(dir, &id, gone).await;
+        remove_strays(dir, &id, &kept).await;
+    }
+
+    // docs that didn't load at startup, or never got any text, only have their meta on disk
+    let Ok(mut entries) = tokio::fs::read_dir(dir).await else { return };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let name = entry.file_name();
+        let Some(id) = name.to_str().and_then(|n| n.strip_suffix(".meta.json")) else { continue };
+        if state.rooms.contains_key(id) {
+            continue;
+        }
+        let path = entry.path();
+        let Ok(bytes) = tokio::fs::read(&path).await else { continue };
+        let Ok(mut meta) = serde_json::from_slice::<DocMeta>(&bytes) else { continue };
+        if meta.attachments.is_empty() {
+            remove_strays(dir, id, &meta.attachments).await;
+            continue;
+        }
+        let Some(text) = text_on_disk(dir, id).await else { continue };
+        let (changed, gone) = sweep(&mut meta.attachments, &text, now);
+        if changed {
+            let tmp  = format!("{}.tmp", path.display());
+            let json = serde_json::to_vec_pretty(&meta).unwrap();
+            let written = match tokio::fs::write(&tmp, &json).await {
+                Ok(()) => tokio::fs::rename(&tmp, &path).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = written {
+                eprintln!("write {} failed: {e}", path.display());
+                continue;
+            }
+        }
+        remove_gone(dir, id, gone).await;
+        remove_strays(dir, id, &meta.attachments).await;
+    }
+}
+
+pub fn spawn_gc(state: AppState) {
+    tokio::spawn(async move {
+        let mut every = tokio::time::interval(GC_EVERY);
+        loop {
+            every.tick().await;
+            collect(&state, "docs", now_secs()).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attachment(id: &str, unreferenced_since: Option<i64>) -> Attachment {
+        Attachment { id: id.to_string(), name: format!("{id}.pdf"), size: 1, created: 0, unreferenced_since }
+    }
+
+    fn ids(attachments: &[Attachment]) -> Vec<(&str, Option<i64>)> {
+        attachments.iter().map(|a| (a.id.as_str(), a.unreferenced_since)).collect()
+    }
+
+    #[test]
+    fn unlinked_attachments_go_after_a_day() {
+        let (a, b) = ("a".repeat(32), "b".repeat(32));
+        let text = format!("see [report](/api/doc/notes/attachments/{a}/report.pdf)");
+        let mut attachments = vec![attachment(&a, None), attachment(&b, None)];
+
+        let (changed, gone) = sweep(&mut attachments, &text, 1000);
+        assert!(changed && gone.is_empty());
+        assert_eq!(ids(&attachments), [(a.as_str(), None), (b.as_str(), Some(1000))]);
+
+        // nothing new to say an hour later
+        assert!(!sweep(&mut attachments, &text, 1000 + 3600).0);
+
+        // linked again before the day is up, it stays
+        let both = format!("{text} and /api/doc/notes/attachments/{b}/x");
+        assert!(sweep(&mut attachments, &both, 2000).0);
+        assert_eq!(ids(&attachments), [(a.as_str(), None), (b.as_str(), None)]);
+
+        sweep(&mut attachments, &text, 3000);
+        assert!(sweep(&mut attachments, &text, 3000 + KEEP_UNLINKED_SECS - 1).1.is_empty());
+        let (changed, gone) = sweep(&mut attachments, &text, 3000 + KEEP_UNLINKED_SECS);
+        assert!(changed);
+        assert_eq!(ids(&gone), [(b.as_str(), Some(3000))]);
+        assert_eq!(ids(&attachments), [(a.as_str(), None)]);
+    }
+
+    #[test]
+    fn an_id_inside_another_id_isnt_a_link() {
+        let short = "0123456789abcdef0123456789abcdef";
+        let text  = format!("/api/doc/notes/attachments/ff{short}ff/x.pdf");
+        let mut attachments = vec![attachment(short, None)];
+        sweep(&mut attachments, &text, 0);
+        assert_eq!(ids(&attachments), [(short, Some(0))]);
+        assert!(mentions(&format!("({short})"), short));
+        assert!(mentions(short, short));
+        assert!(!mentions(&format!("{short}0"), short));
+    }
+
+    #[tokio::test]
+    async fn docs_that_arent_loaded_get_swept_too() {
+        let dir = std::env::temp_dir().join(format!("reality-docs-{}", uuid::Uuid::new_v4().simple()));
+        let dir = dir.to_str().unwrap().to_string();
+        let (a, b) = ("a".repeat(32), "b".repeat(32));
+        std::fs::create_dir_all(format!("{dir}/notes.files")).unwrap();
+        std::fs::write(format!("{dir}/notes.files/{a}"), "a").unwrap();
+        std::fs::write(format!("{dir}/notes.files/{b}"), "b").unwrap();
+        std::fs::write(format!("{dir}/notes.md"), format!("[a](/api/doc/notes/attachments/{a}/a.pdf)")).unwrap();
+        let meta = DocMeta { attachments: vec![attachment(&a, None), attachment(&b, None)], ..DocMeta::default() };
+        let meta_path = format!("{dir}/notes.meta.json");
+        std::fs::write(&meta_path, serde_json::to_vec(&meta).unwrap()).unwrap();
+        let on_disk = || serde_json::from_slice::<DocMeta>(&std::fs::read(&meta_path).unwrap()).unwrap().attachments;
+
+        let state = AppState::in_memory();
+        collect(&state, &dir, 1000).await;
+        assert_eq!(ids(&on_disk()), [(a.as_str(), None), (b.as_str(), Some(1000))]);
+
+        collect(&state, &dir, 1000 + KEEP_UNLINKED_SECS).await;
+        assert_eq!(ids(&on_disk()), [(a.as_str(), None)]);
+        assert!(std::path::Path::new(&format!("{dir}/notes.files/{a}")).exists());
+        assert!(!std::path::Path::new(&format!("{dir}/notes.files/{b}")).exists());
+
+        // files the meta doesn't know about go once they've sat there a while
+        let stray = format!("{dir}/notes.files/{}", "c".repeat(32));
+        std::fs::write(&stray, "c").unwrap();
+        std::fs::write(format!("{stray}.tmp"), "half").unwrap();
+        collect(&state, &dir, 2000 + KEEP_UNLINKED_SECS).await;
+        assert!(std::path::Path::new(&stray).exists());
+        let an_hour_ago = std::time::SystemTime::now() - STRAY_AFTER;
+        for path in [stray.clone(), format!("{stray}.tmp"), format!("{dir}/notes.files/{a}")] {
+            std::fs::File::options().write(true).open(path).unwrap().set_modified(an_hour_ago).unwrap();
+        }
+        collect(&state, &dir, 3000 + KEEP_UNLINKED_SECS).await;
+        assert!(!std::path::Path::new(&stray).exists());
+        assert!(!std::path::Path::new(&format!("{stray}.tmp")).exists());
+        assert!(std::path::Path::new(&format!("{dir}/notes.files/{a}")).exists());
+
+        // a history that won't parse leaves everything alone
+        std::fs::write(format!("{dir}/notes.am"), "not automerge").unwrap();
+        collect(&state, &dir, 5000 + KEEP_UNLINKED_SECS * 2).await;
+        assert_eq!(ids(&on_disk()), [(a.as_str(), None)]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn export_links_point_inside_the_archive() {
+        let id   = "c".repeat(32);
+        let text = format!(
+            "[a](/api/doc/notes/attachments/{id}/a.pdf) and <https://reality.example/api/doc/notes/attachments/{id}/renamed.pdf> and [b](/api/doc/notes/attachments/{id})"
+        );
+        assert_eq!(
+            relink(&text, &format!("/api/doc/notes/attachments/{id}"), "attachments/a.pdf"),
+            "[a](attachments/a.pdf) and <attachments/a.pdf> and [b](attachments/a.pdf)"
+        );
+        let hash = "d".repeat(64);
+        assert_eq!(linked_assets(&format!("![x](/assets/{hash}) ![y](/assets/{hash}) /assets/short")), [hash]);
+    }
+}
@@ -0,0 +1,506 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, OnceLock, RwLock};
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use axum::{
+    extract::{ConnectInfo, FromRequestParts, Query, State},
+    http::{header, request::Parts, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use reality::protocol::Role;
+use crate::api_tokens::{ApiTokens, TokenGrant};
+use crate::lock::DocPasses;
+use crate::meta::DocMeta;
+use crate::rate::{client_ip, too_many_guesses};
+use crate::state::{now_secs, AppState};
+
+const USERS_PATH: &str = "docs/users.json";
+const SESSION_COOKIE: &str = "reality_session";
+const SESSION_TTL_SECS: i64 = 30 * 24 * 60 * 60;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct User {
+    pub id:            String,
+    pub username:      String,
+    pub password_hash: String,
+    pub created:       i64,
+}
+
+// the bits of a user that are fine to hand to the rest of the server and the client
+#[derive(Serialize, Clone, Debug)]
+pub struct AuthUser {
+    pub id:       String,
+    pub username: String,
+}
+
+#[derive(Clone)]
+struct Session {
+    user_id: String,
+    expires: i64,
+}
+
+// accounts live in docs/users.json next to the docs, sessions only live in memory
+#[derive(Clone, Default)]
+pub struct Accounts {
+    users:    Arc<RwLock<HashMap<String, User>>>,
+    sessions: Arc<DashMap<String, Session>>,
+    // None keeps the accounts in memory only, which is what the tests want
+    path:     Option<&'static str>,
+}
+
+// checked against when the username doesn't exist, so a wrong name takes as long as a wrong password
+static DUMMY_HASH: OnceLock<String> = OnceLock::new();
+
+fn dummy_hash() -> &'static str {
+    DUMMY_HASH.get_or_init(|| hash_password("nobody's password").unwrap_or_default())
+}
+
+impl Accounts {
+    pub fn load() -> Self {
+        let users: Vec<User> = match std::fs::read(USERS_PATH) {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+                eprintln!("Failed to parse {USERS_PATH}: {e}");
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+        println!("Loaded {} user account(s)", users.len());
+        let users = users.into_iter().map(|u| (u.username.to_lowercase(), u)).collect();
+        // made now rather than making the first login with an unknown name slower
+        dummy_hash();
+        Self { users: Arc::new(RwLock::new(users)), sessions: Arc::default(), path: Some(USERS_PATH) }
+    }
+
+    // only called from register, which already runs on a blocking thread
+    fn persist(&self) {
+        let Some(path) = self.path else { return };
+        let users: Vec<User> = self.users.read().unwrap().values().cloned().collect();
+        let json = serde_json::to_vec_pretty(&users).unwrap();
+        if let Err(e) = std::fs::create_dir_all("docs") {
+            eprintln!("create_dir_all failed: {e}");
+            return;
+        }
+        if let Err(e) = std::fs::write(path, &json) {
+            eprintln!("write {path} failed: {e}");
+        }
+    }
+
+    pub fn register(&self, username: &str, password: &str) -> Result<AuthUser, &'static str> {
+        let username = username.trim();
+        if username.is_empty() || username.len() > 64 {
+            return Err("username must be 1-64 characters");
+        }
+        if password.len() < 8 {
+            return Err("password must be at least 8 characters");
+        }
+        let key = username.to_lowercase();
+        let password_hash = hash_password(password).ok_or("couldn't hash password")?;
+        let user = User {
+            id: uuid::Uuid::new_v4().simple().to_string(),
+            username: username.to_string(),
+            password_hash,
+            created: now_secs(),
+        };
+        let auth = AuthUser { id: user.id.clone(), username: user.username.clone() };
+        {
+            let mut users = self.users.write().unwrap();
+            if users.contains_key(&key) {
+                return Err("that username is taken");
+            }
+            users.insert(key, user);
+        }
+        self.persist();
+        Ok(auth)
+    }
+
+    pub fn verify(&self, username: &str, password: &str) -> Option<AuthUser> {
+        let found = self.users.read().unwrap().get(&username.trim().to_lowercase()).map(|user| {
+            (user.password_hash.clone(), AuthUser { id: user.id.clone(), username: user.username.clone() })
+        });
+        let Some((hash, user)) = found else {
+            password_matches(dummy_hash(), password);
+            return None;
+        };
+        password_matches(&hash, password).then_some(user)
+    }
+
+    pub fn start_session(&self, user: &AuthUser) -> String {
+        let token = format!("{}{}", uuid::Uuid::new_v4().simple(), uuid::Uuid::new_v4().simple());
+        self.sessions.insert(token.clone(), Session {
+            user_id: user.id.clone(),
+            expires: now_secs() + SESSION_TTL_SECS,
+        });
+        token
+    }
+
+    pub fn end_session(&self, token: &str) {
+        self.sessions.remove(token);
+    }
+
+    pub fn user_for_session(&self, token: &str) -> Option<AuthUser> {
+        let user_id = {
+            let session = self.sessions.get(token)?;
+            if session.expires < now_secs() {
+                drop(session);
+                self.sessions.remove(token);
+                return None;
+            }
+            session.user_id.clone()
+        };
+        self.user_by_id(&user_id)
+    }
+
+    pub fn user_by_id(&self, user_id: &str) -> Option<AuthUser> {
+        self.users
+            .read()
+            .unwrap()
+            .values()
+            .find(|u| u.id == user_id)
+            .map(|u| AuthUser { id: u.id.clone(), username: u.username.clone() })
+    }
+
+    pub fn find_by_username(&self, username: &str) -> Option<AuthUser> {
+        let users = self.users.read().unwrap();
+        let user = users.get(&username.trim().to_lowercase())?;
+        Some(AuthUser { id: user.id.clone(), username: user.username.clone() })
+    }
+
+    pub fn username_for(&self, user_id: &str) -> Option<String> {
+        self.user_by_id(user_id).map(|u| u.username)
+    }
+
+    // whoever the session cookie on this request belongs to
+    pub fn user_from_headers(&self, headers: &HeaderMap) -> Option<AuthUser> {
+        self.user_for_session(&session_token(headers)?)
+    }
+}
+
+// whoever is behind a request, from the session cookie or an api token
+#[derive(Clone)]
+pub struct Caller {
+    pub user:  Option<AuthUser>,
+    // set when the request came with an api token, user is then whoever made a user token
+    pub token: Option<TokenGrant>,
+    headers:   HeaderMap,
+    tokens:    ApiTokens,
+}
+
+impl Caller {
+    pub fn user_id(&self) -> Option<&str> {
+        self.user.as_ref().map(|u| u.id.as_str())
+    }
+
+    // None means no access at all
+    pub fn role(&self, doc_id: &str, meta: &DocMeta) -> Option<Role> {
+        let Some(token) = &self.token else {
+            return meta.role_for(self.user_id());
+        };
+        if !self.tokens.is_live(&token.id) {
+            return None;
+        }
+        match &token.doc_id {
+            // doc tokens stand on their own, their scope is all they get
+            Some(token_doc) => (token_doc == doc_id).then(|| token.scope.cap()),
+            None            => meta.role_for(self.user_id()).map(|role| role.min(token.scope.cap())),
+        }
+    }
+
+    // doc tokens were made by the owner, so they count as knowing the password
+    pub fn needs_password(&self, doc_id: &str, meta: &DocMeta, passes: &DocPasses) -> bool {
+        if self.token.as_ref().is_some_and(|t| t.doc_id.is_some()) {
+            return false;
+        }
+        meta.needs_password(self.user_id()) && !passes.allows(&self.headers, doc_id)
+    }
+}
+
+#[cfg(test)]
+impl Caller {
+    // whoever a request with these headers would turn out to be
+    pub async fn for_test(state: &AppState, headers: &[(&str, &str)]) -> Self {
+        let mut req = axum::http::Request::builder().uri("/");
+        for (name, value) in headers {
+            req = req.header(*name, *value);
+        }
+        let mut parts = req.body(()).unwrap().into_parts().0;
+        Self::from_request_parts(&mut parts, state).await.ok().unwrap()
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenQuery {
+    access_token: Option<String>,
+}
+
+// "Authorization: Bearer <token>" or ?access_token=, the query is for websockets where
+// browsers and most ws libraries can't set headers
+fn presented_token(parts: &Parts) -> Option<String> {
+    let bearer = parts
+        .headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(|t| t.trim().to_string());
+    bearer.or_else(|| Query::<TokenQuery>::try_from_uri(&parts.uri).ok()?.0.access_token)
+}
+
+#[axum::async_trait]
+impl FromRequestParts<AppState> for Caller {
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Response> {
+        let (user, token) = match presented_token(parts) {
+            Some(raw) => {
+                // a bad token is an error rather than quietly carrying on signed out
+                let Some(grant) = state.tokens.lookup(&raw) else {
+                    return Err((StatusCode::UNAUTHORIZED, "unknown api token").into_response());
+                };
+                let user = match grant.doc_id {
+                    Some(_) => None,
+                    None    => state.accounts.user_by_id(&grant.user_id),
+                };
+                (user, Some(grant))
+            }
+            None => (state.accounts.user_from_headers(&parts.headers), None),
+        };
+        Ok(Self { user, token, headers: parts.headers.clone(), tokens: state.tokens.clone() })
+    }
+}
+
+// argon2 phc string, slow on purpose so only call this from a blocking thread
+pub fn hash_password(password: &str) -> Option<String> {
+    let salt = SaltString::encode_b64(uuid::Uuid::new_v4().as_bytes()).ok()?;
+    Some(Argon2::default().hash_password(password.as_bytes(), &salt).ok()?.to_string())
+}
+
+pub fn password_matches(hash: &str, password: &str) -> bool {
+    let Ok(parsed) = PasswordHash::new(hash) else { return false; };
+    Argon2::default().verify_password(password.as_bytes(), &parsed).is_ok()
+}
+
+pub fn cookie_value(headers: &HeaderMap, cookie: &str) -> Option<String> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(name, _)| *name == cookie)
+        .map(|(_, value)| value.to_string())
+}
+
+fn session_token(headers: &HeaderMap) -> Option<String> {
+    cookie_value(headers, SESSION_COOKIE)
+}
+
+// whether the page talking to us was loaded over https, directly or through a proxy. browsers
+// drop Secure cookies sent over plain http (except to localhost), so those only get them on https
+pub fn is_https(headers: &HeaderMap) -> bool {
+    let proto  = headers.get("x-forwarded-proto").and_then(|v| v.to_str().ok());
+    let origin = headers.get(header::ORIGIN).and_then(|v| v.to_str().ok());
+    proto.is_some_and(|p| p.eq_ignore_ascii_case("https")) || origin.is_some_and(|o| o.starts_with("https://"))
+}
+
+fn session_cookie(token: &str, max_age: i64, secure: bool) -> String {
+    let secure = if secure { " Secure;" } else { "" };
+    format!("{SESSION_COOKIE}={token}; Path=/; HttpOnly;{secure} SameSite=Lax; Max-Age={max_age}")
+}
+
+pub fn auth_router(state: AppState) -> Router {
+    Router::new()
+        .route("/api/auth/register", post(register))
+        .route("/api/auth/login", post(login))
+        .route("/api/auth/logout", post(logout))
+        .route("/api/auth/me", get(me))
+        .with_state(state)
+}
+
+#[derive(Deserialize)]
+struct Credentials {
+    username: String,
+    password: String,
+}
+
+fn logged_in(accounts: &Accounts, headers: &HeaderMap, user: AuthUser) -> Response {
+    let token = accounts.start_session(&user);
+    (
+        [(header::SET_COOKIE, session_cookie(&token, SESSION_TTL_SECS, is_https(headers)))],
+        Json(user),
+    ).into_response()
+}
+
+async fn register(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(creds): Json<Credentials>,
+) -> impl IntoResponse {
+    // argon2 is deliberately slow, keep it off the async workers
+    let accounts = state.accounts.clone();
+    let result = tokio::task::spawn_blocking(move || accounts.register(&creds.username, &creds.password)).await;
+    match result {
+        Ok(Ok(user)) => logged_in(&state.accounts, &headers, user),
+        Ok(Err(e))   => (StatusCode::BAD_REQUEST, e).into_response(),
+        Err(_)       => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+async fn login(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(creds): Json<Credentials>,
+) -> impl IntoResponse {
+    let ip     = client_ip(addr, &headers, &state.limits);
+    let target = format!("user:{}", creds.username.trim().to_lowercase());
+    if let Err(wait) = state.guesses.take(ip, &target) {
+        return too_many_guesses(wait);
+    }
+    let accounts = state.accounts.clone();
+    let result = tokio::task::spawn_blocking(move || accounts.verify(&creds.username, &creds.password)).await;
+    match result {
+        Ok(Some(user)) => {
+            state.guesses.right(ip, &target);
+            logged_in(&state.accounts, &headers, user)
+        }
+        Ok(None)       => (StatusCode::UNAUTHORIZED, "wrong username or password").into_response(),
+        Err(_)         => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+async fn logout(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    if let Some(token) = session_token(&headers) {
+        state.accounts.end_session(&token);
+    }
+    (
+        StatusCode::NO_CONTENT,
+        [(header::SET_COOKIE, session_cookie("", 0, is_https(&headers)))],
+    ).into_response()
+}
+
+async fn me(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    match state.accounts.user_from_headers(&headers) {
+        Some(user) => Json(user).into_response(),
+        None       => StatusCode::UNAUTHORIZED.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parts(headers: &[(&str, &str)]) -> Parts {
+        let mut req = axum::http::Request::builder().uri("/api/doc/notes/history");
+        for (name, value) in headers {
+            req = req.header(*name, *value);
+        }
+        req.body(()).unwrap().into_parts().0
+    }
+
+    #[test]
+    fn register_then_verify() {
+        let accounts = Accounts::default();
+        assert_eq!(accounts.register("alice", "short").err(), Some("password must be at least 8 characters"));
+        assert_eq!(accounts.register("  ", "long enough").err(), Some("username must be 1-64 characters"));
+        let alice = accounts.register("Alice", "correct horse").unwrap();
+        assert_eq!(accounts.register("ALICE", "another one").err(), Some("that username is taken"));
+
+        assert_eq!(accounts.verify(" alice ", "correct horse").map(|u| u.id), Some(alice.id.clone()));
+        assert!(accounts.verify("alice", "wrong horse").is_none());
+        assert!(accounts.verify("bob", "correct horse").is_none());
+        // an unknown name still costs a hash check
+        assert!(DUMMY_HASH.get().is_some());
+        assert_eq!(accounts.find_by_username("ALICE").map(|u| u.username), Some("Alice".to_string()));
+        assert_eq!(accounts.username_for(&alice.id).as_deref(), Some("Alice"));
+    }
+
+    #[test]
+    fn sessions_end_and_expire() {
+        let accounts = Accounts::default();
+        let user = AuthUser { id: "u1".into(), username: "alice".into() };
+        accounts.users.write().unwrap().insert("alice".into(), User {
+            id: user.id.clone(), username: user.username.clone(), password_hash: String::new(), created: 0,
+        });
+
+        let token = accounts.start_session(&user);
+        assert_eq!(accounts.user_for_session(&token).map(|u| u.id), Some("u1".to_string()));
+        accounts.end_session(&token);
+        assert!(accounts.user_for_session(&token).is_none());
+
+        let token = accounts.start_session(&user);
+        accounts.sessions.get_mut(&token).unwrap().expires = now_secs() - 1;
+        assert!(accounts.user_for_session(&token).is_none());
+        assert!(!accounts.sessions.contains_key(&token));
+        assert!(accounts.user_for_session("made up").is_none());
+    }
+
+    #[tokio::test]
+    async fn callers_come_from_the_session_cookie() {
+        let state = AppState::in_memory();
+        let user  = AuthUser { id: "u1".into(), username: "alice".into() };
+        state.accounts.users.write().unwrap().insert("alice".into(), User {
+            id: user.id.clone(), username: user.username.clone(), password_hash: String::new(), created: 0,
+        });
+        let token  = state.accounts.start_session(&user);
+        let cookie = format!("theme=dark; {SESSION_COOKIE}={token}");
+
+        let caller = Caller::from_request_parts(&mut parts(&[("cookie", &cookie)]), &state).await.ok().unwrap();
+        assert_eq!(caller.user_id(), Some("u1"));
+        assert!(caller.token.is_none());
+
+        let caller = Caller::from_request_parts(&mut parts(&[]), &state).await.ok().unwrap();
+        assert!(caller.user.is_none());
+        let caller = Caller::from_request_parts(&mut parts(&[("cookie", "reality_session=stale")]), &state).await.ok().unwrap();
+        assert!(caller.user.is_none());
+
+        // a bad token isn't quietly treated as signed out, even with a good cookie
+        let rejected = Caller::from_request_parts(&mut parts(&[("cookie", &cookie), ("authorization", "Bearer nope")]), &state).await;
+        assert_eq!(rejected.err().map(|r| r.status()), Some(StatusCode::UNAUTHORIZED));
+    }
+
+    #[tokio::test]
+    async fn logins_stop_after_too_many_wrong_passwords() {
+        let state = AppState::in_memory();
+        state.accounts.register("alice", "correct horse").unwrap();
+        let login_as = |username: &str, password: &str| login(
+            State(state.clone()),
+            ConnectInfo("10.0.0.1:5000".parse().unwrap()),
+            HeaderMap::new(),
+            Json(Credentials { username: username.into(), password: password.into() }),
+        );
+        assert_eq!(login_as("alice", "correct horse").await.into_response().status(), StatusCode::OK);
+
+        let mut last = StatusCode::UNAUTHORIZED;
+        for _ in 0..20 {
+            last = login_as("alice", "wrong horse").await.into_response().status();
+            if last != StatusCode::UNAUTHORIZED {
+                break;
+            }
+        }
+        assert_eq!(last, StatusCode::TOO_MANY_REQUESTS);
+        let resp = login_as("Alice", "correct horse").await.into_response();
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(resp.headers().contains_key(header::RETRY_AFTER));
+    }
+
+    #[test]
+    fn cookies_are_secure_only_over_https() {
+        let headers = |pairs: &[(&str, &str)]| parts(pairs).headers;
+        assert!(is_https(&headers(&[("origin", "https://notes.example.com")])));
+        assert!(is_https(&headers(&[("origin", "http://10.0.0.5:3001"), ("x-forwarded-proto", "https")])));
+        assert!(!is_https(&headers(&[("origin", "http://10.0.0.5:3001")])));
+        assert!(!is_https(&headers(&[])));
+        assert!(session_cookie("t", 60, true).contains("; Secure;"));
+        assert!(!session_cookie("t", 60, false).contains("Secure"));
+    }
+}
@@ -0,0 +1,132 @@
+use serde::{Deserialize, Serialize};
+use similar::{ChangeTag, TextDiff};
+
+// how many unchanged lines to keep around each hunk
+const CONTEXT_LINES: usize = 3;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum LineKind {
+    Equal,
+    Insert,
+    Delete,
+}
+
+// a run of text inside a line, changed marks the words that actually differ
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Segment {
+    pub text:    String,
+    pub changed: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct HunkLine {
+    pub kind:     LineKind,
+    pub segments: Vec<Segment>,
+}
+
+// line numbers are 1 based like every other diff tool
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Hunk {
+    pub old_start: usize,
+    pub old_len:   usize,
+    pub new_start: usize,
+    pub new_len:   usize,
+    pub lines:     Vec<HunkLine>,
+}
+
+// the single edit that turns old into new: (char index, chars to delete, text to insert).
+// only trims the common prefix and suffix, which is all a keystroke or paste ever needs
+pub fn splice(old: &str, new: &str) -> (usize, usize, String) {
+    let old_chars: Vec<char> = old.chars().collect();
+    let new_chars: Vec<char> = new.chars().collect();
+    let prefix = old_chars
+        .iter()
+        .zip(new_chars.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    // only walk the suffix of what's left so we don't rescan the whole .md file
+    let old_suffix = &old_chars[prefix..];
+    let new_suffix = &new_chars[prefix..];
+    let suffix = old_suffix
+        .iter()
+        .rev()
+        .zip(new_suffix.iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let delete = old_suffix.len() - suffix;
+    let insert: String = new_suffix[..new_suffix.len() - suffix].iter().collect();
+    (prefix, delete, insert)
+}
+
+// line diff first, then word diff inside the lines that changed
+pub fn hunks(old: &str, new: &str) -> Vec<Hunk> {
+    let diff = TextDiff::from_lines(old, new);
+    diff.grouped_ops(CONTEXT_LINES)
+        .iter()
+        .map(|group| {
+            let first = group.first().unwrap();
+            let last  = group.last().unwrap();
+            let old_range = first.old_range().start..last.old_range().end;
+            let new_range = first.new_range().start..last.new_range().end;
+            let lines = group
+                .iter()
+                .flat_map(|op| diff.iter_inline_changes(op))
+                .map(|change| HunkLine {
+                    kind: match change.tag() {
+                        ChangeTag::Equal  => LineKind::Equal,
+                        ChangeTag::Insert => LineKind::Insert,
+                        ChangeTag::Delete => LineKind::Delete,
+                    },
+                    segments: change
+                        .iter_strings_lossy()
+                        .map(|(changed, text)| Segment {
+                            text: text.trim_end_matches('\n').to_string(),
+                            changed,
+                        })
+                        .filter(|s| !s.text.is_empty())
+                        .collect(),
+                })
+                .collect();
+            Hunk {
+                old_start: old_range.start + 1,
+                old_len:   old_range.len(),
+                new_start: new_range.start + 1,
+                new_len:   new_range.len(),
+                lines,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splice_finds_the_changed_middle() {
+        assert_eq!(splice("hello world", "hello there world"), (6, 0, "there ".to_string()));
+        assert_eq!(splice("abc", "abc"), (3, 0, String::new()));
+        assert_eq!(splice("héllo", "hello"), (1, 1, "e".to_string()));
+    }
+
+    #[test]
+    fn hunks_mark_changed_words() {
+        let hunks = hunks("a\nhello world\nc\n", "a\nhello there world\nc\n");
+        assert_eq!(hunks.len(), 1);
+        let kinds: Vec<LineKind> = hunks[0].lines.iter().map(|l| l.kind).collect();
+        assert_eq!(kinds, [LineKind::Equal, LineKind::Delete, LineKind::Insert, LineKind::Equal]);
+        let changed: Vec<&str> = hunks[0].lines[2]
+            .segments
+            .iter()
+            .filter(|s| s.changed)
+            .map(|s| s.text.as_str())
+            .collect();
+        assert_eq!(changed, ["there "]);
+    }
+
+    #[test]
+    fn identical_text_has_no_hunks() {
+        assert!(hunks("same\n", "same\n").is_empty());
+    }
+}
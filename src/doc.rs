@@ -0,0 +1,347 @@
+use std::collections::HashMap;
+use automerge::{AutoCommit, ChangeHash, ObjId, ObjType, ReadDoc};
+use automerge::transaction::{CommitOptions, Transactable};
+use serde::{Deserialize, Serialize};
+use crate::diff;
+
+// changes from the same person closer together than this count as one editing session
+const SESSION_GAP_SECS: i64 = 5 * 60;
+
+// every automerge document or change chunk starts with these
+const AUTOMERGE_MAGIC: [u8; 4] = [0x85, 0x6f, 0x4a, 0x83];
+
+pub struct Doc {
+    am: AutoCommit,
+    text_obj: ObjId,
+}
+
+// why merge_changes_within turned some bytes away
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MergeError {
+    // not automerge changes, or not something a client should be sending
+    Invalid,
+    // would have made the text longer than allowed
+    TooLong,
+}
+
+// one row in the history timeline, heads are hex change hashes you can pass back to text_at
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Session {
+    pub actor:   String,
+    pub start:   i64,
+    pub end:     i64,
+    pub changes: usize,
+    pub heads:   Vec<String>,
+}
+
+// a run of text that came from one change, for the blame view
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Attribution {
+    pub text:  String,
+    pub actor: String,
+    pub time:  i64,
+}
+
+impl Default for Doc {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// the one crdt wrapper, the editor and the server both go through this so they can't drift apart
+impl Doc {
+    pub fn new() -> Self {
+        let mut am = AutoCommit::new();
+        let text_obj = am
+            .put_object(automerge::ROOT, "text", ObjType::Text)
+            .unwrap();
+        Self { am, text_obj }
+    }
+
+    pub fn get_text(&self) -> String {
+        self.am.text(&self.text_obj).unwrap_or_default()
+    }
+
+    // makes the edit as one change stamped with `time` (unix seconds, the caller knows its own clock)
+    // and hands back just that change, which is all anyone else needs to merge it
+    pub fn splice_text(&mut self, insert_at: usize, delete_count: usize, insert: &str, time: i64) -> Vec<u8> {
+        let before = self.am.get_heads();
+        self.am
+            .splice_text(&self.text_obj, insert_at, delete_count as isize, insert)
+            .unwrap();
+        self.am.commit_with(CommitOptions::default().with_time(time));
+        self.am.save_after(&before)
+    }
+
+    pub fn save(&mut self) -> Vec<u8> {
+        self.am.save()
+    }
+
+    pub fn load_from_bytes(bytes: &[u8]) -> Option<Self> {
+        let am = AutoCommit::load(bytes).ok()?;
+        let text_obj = am.get(automerge::ROOT, "text").ok()??.1;
+        Some(Self { am, text_obj })
+    }
+
+    // takes either a full save or single changes, None if the bytes aren't automerge at all
+    pub fn merge_changes(&mut self, bytes: &[u8]) -> Option<String> {
+        // automerge skips chunks it can't parse without complaining, so check the magic bytes ourselves
+        if !bytes.starts_with(&AUTOMERGE_MAGIC) {
+            return None;
+        }
+        self.am.load_incremental(bytes).ok()?;
+        Some(self.get_text())
+    }
+
+    // like merge_changes, but only takes change chunks and leaves the doc alone if the
+    // text would end up over max_chars
+    pub fn merge_changes_within(&mut self, bytes: &[u8], max_chars: usize) -> Result<String, MergeError> {
+        let growth = change_size(bytes).ok_or(MergeError::Invalid)?;
+        if self.am.length(&self.text_obj) + growth > max_chars {
+            // growth is only an upper bound (deletes, changes we already have), so near the
+            // limit try it for real on a copy. forking is slow on big docs, hence not every time
+            let mut trial = self.am.fork();
+            trial.load_incremental(bytes).map_err(|_| MergeError::Invalid)?;
+            if trial.length(&self.text_obj) > max_chars {
+                return Err(MergeError::TooLong);
+            }
+        }
+        self.merge_changes(bytes).ok_or(MergeError::Invalid)
+    }
+
+    // walks every change in order and groups runs by the same actor into sessions,
+    // heads are tracked as we go so each session knows what the doc looked like right after it
+    pub fn history(&mut self) -> Vec<Session> {
+        let mut sessions: Vec<Session> = Vec::new();
+        let mut heads: Vec<ChangeHash> = Vec::new();
+        for change in self.am.get_changes(&[]) {
+            heads.retain(|h| !change.deps().contains(h));
+            heads.push(change.hash());
+            let actor = change.actor_id().to_hex_string();
+            let time  = change.timestamp();
+            let head_strs = heads.iter().map(|h| h.to_string()).collect();
+            match sessions.last_mut() {
+                Some(last) if last.actor == actor && time - last.end <= SESSION_GAP_SECS => {
+                    // the change that creates the text object has no time, don't let it anchor the session
+                    if last.start == 0 {
+                        last.start = time;
+                    }
+                    last.end = last.end.max(time);
+                    last.changes += 1;
+                    last.heads = head_strs;
+                }
+                _ => sessions.push(Session {
+                    actor,
+                    start:   time,
+                    end:     time,
+                    changes: 1,
+                    heads:   head_strs,
+                }),
+            }
+        }
+        sessions
+    }
+
+    // actors of every change that landed after `heads`, used to learn who a client is
+    pub fn actors_since(&mut self, heads: &[String]) -> Vec<String> {
+        let heads = self.parse_heads(heads).unwrap_or_default();
+        let mut actors: Vec<String> = self
+            .am
+            .get_changes(&heads)
+            .iter()
+            .map(|c| c.actor_id().to_hex_string())
+            .collect();
+        actors.dedup();
+        actors
+    }
+
+    // who typed each character still in the doc, neighbours from the same change are merged into one run
+    pub fn blame(&mut self) -> Vec<Attribution> {
+        let times: HashMap<ChangeHash, i64> = self
+            .am
+            .get_changes(&[])
+            .iter()
+            .map(|c| (c.hash(), c.timestamp()))
+            .collect();
+        let mut runs: Vec<(Option<ChangeHash>, Attribution)> = Vec::new();
+        for item in self.am.list_range(&self.text_obj, ..) {
+            let text  = item.value.to_str().unwrap_or_default();
+            let hash  = self.am.hash_for_opid(&item.id);
+            let actor = match &item.id {
+                ObjId::Id(_, actor, _) => actor.to_hex_string(),
+                ObjId::Root            => String::new(),
+            };
+            match runs.last_mut() {
+                Some((last_hash, run)) if *last_hash == hash => run.text.push_str(text),
+                _ => runs.push((hash, Attribution {
+                    text: text.to_string(),
+                    actor,
+                    time: hash.and_then(|h| times.get(&h).copied()).unwrap_or(0),
+                })),
+            }
+        }
+        runs.into_iter().map(|(_, run)| run).collect()
+    }
+
+    pub fn heads(&mut self) -> Vec<String> {
+        self.am.get_heads().iter().map(|h| h.to_string()).collect()
+    }
+
+    // the text as it was at some point in history, None if a hash isn't in this doc
+    pub fn text_at(&mut self, heads: &[String]) -> Option<String> {
+        let heads = self.parse_heads(heads)?;
+        Some(self.am.text_at(&self.text_obj, &heads).unwrap_or_default())
+    }
+
+    // rolls the text back to how it was at `heads`, but as a fresh change on top of
+    // everything so other clients just merge it and the restore can itself be undone
+    pub fn restore(&mut self, heads: &[String], time: i64) -> Option<String> {
+        let old     = self.text_at(heads)?;
+        let current = self.get_text();
+        let (insert_at, delete_count, insert) = diff::splice(&current, &old);
+        if delete_count > 0 || !insert.is_empty() {
+            self.splice_text(insert_at, delete_count, &insert, time);
+        }
+        Some(self.get_text())
+    }
+
+    fn parse_heads(&mut self, heads: &[String]) -> Option<Vec<ChangeHash>> {
+        heads
+            .iter()
+            .map(|h| h.parse::<ChangeHash>().ok())
+            .map(|h| h.filter(|h| self.am.get_change_by_hash(h).is_some()))
+            .collect()
+    }
+}
+
+// total uncompressed size of a run of change chunks, which is at least as many characters
+// as they can add. None if anything in there isn't a change, like a whole document
+fn change_size(mut bytes: &[u8]) -> Option<usize> {
+    if bytes.is_empty() {
+        return None;
+    }
+    let mut total = 0;
+    while !bytes.is_empty() {
+        let len = chunk_len(bytes)?;
+        let change = automerge::Change::try_from(&bytes[..len]).ok()?;
+        total += change.raw_bytes().len();
+        bytes = &bytes[len..];
+    }
+    Some(total)
+}
+
+// chunk header is magic, 4 checksum bytes, a type byte (1 change, 2 compressed change)
+// and a uleb128 length of what follows
+fn chunk_len(bytes: &[u8]) -> Option<usize> {
+    if !bytes.starts_with(&AUTOMERGE_MAGIC) || !matches!(bytes.get(8), Some(1) | Some(2)) {
+        return None;
+    }
+    let mut data_len: usize = 0;
+    let mut header_len = 9;
+    for shift in (0..64).step_by(7) {
+        let byte = *bytes.get(header_len)?;
+        header_len += 1;
+        data_len |= ((byte & 0x7f) as usize).checked_shl(shift)?;
+        if byte & 0x80 == 0 {
+            let len = header_len.checked_add(data_len)?;
+            return (len <= bytes.len()).then_some(len);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splice_returns_change_another_doc_can_merge() {
+        let mut server = Doc::new();
+        let mut client = Doc::load_from_bytes(&server.save()).unwrap();
+        let change = client.splice_text(0, 0, "hello", 10);
+        assert_eq!(server.merge_changes(&change).as_deref(), Some("hello"));
+    }
+
+    #[test]
+    fn merge_accepts_full_saves_and_rejects_garbage() {
+        let mut a = Doc::new();
+        a.splice_text(0, 0, "abc", 10);
+        let mut b = Doc::load_from_bytes(&a.save()).unwrap();
+        b.splice_text(3, 0, "def", 20);
+        assert_eq!(a.merge_changes(&b.save()).as_deref(), Some("abcdef"));
+        assert_eq!(a.merge_changes(b"not automerge"), None);
+    }
+
+    #[test]
+    fn merge_within_enforces_the_limit() {
+        let mut server = Doc::new();
+        let mut client = Doc::load_from_bytes(&server.save()).unwrap();
+        let small = client.splice_text(0, 0, "hello", 10);
+        let big = client.splice_text(5, 0, &"x".repeat(50), 20);
+        assert_eq!(server.merge_changes_within(&small, 10).as_deref(), Ok("hello"));
+        assert_eq!(server.merge_changes_within(&big, 10), Err(MergeError::TooLong));
+        assert_eq!(server.get_text(), "hello");
+        // a delete of the same size fits even though the bytes are big
+        let trim = client.splice_text(0, 55, "", 30);
+        let mut both = big.clone();
+        both.extend(trim);
+        assert_eq!(server.merge_changes_within(&both, 10).as_deref(), Ok(""));
+    }
+
+    #[test]
+    fn merge_within_only_takes_changes() {
+        let mut server = Doc::new();
+        let mut other = Doc::load_from_bytes(&server.save()).unwrap();
+        other.splice_text(0, 0, "abc", 10);
+        assert_eq!(server.merge_changes_within(&other.save(), 100), Err(MergeError::Invalid));
+        assert_eq!(server.merge_changes_within(b"garbage", 100), Err(MergeError::Invalid));
+        assert_eq!(server.merge_changes_within(&[], 100), Err(MergeError::Invalid));
+    }
+
+    #[test]
+    fn history_groups_by_actor_and_time() {
+        let mut doc = Doc::new();
+        doc.splice_text(0, 0, "one ", 100);
+        doc.splice_text(4, 0, "two ", 110);
+        doc.splice_text(8, 0, "three", 100 + SESSION_GAP_SECS * 2);
+        let sessions = doc.history();
+        assert_eq!(sessions.len(), 2);
+        // the first session also holds the change that created the text object
+        assert_eq!(sessions[0].changes, 3);
+        assert_eq!(sessions[0].start, 100);
+        assert_eq!(doc.text_at(&sessions[0].heads).as_deref(), Some("one two "));
+        assert_eq!(doc.text_at(&sessions[1].heads).as_deref(), Some("one two three"));
+    }
+
+    #[test]
+    fn text_at_rejects_unknown_heads() {
+        let mut doc = Doc::new();
+        assert_eq!(doc.text_at(&["zz".to_string()]), None);
+        assert_eq!(doc.text_at(&["00".repeat(32)]), None);
+    }
+
+    #[test]
+    fn restore_is_a_new_change() {
+        let mut doc = Doc::new();
+        doc.splice_text(0, 0, "keep this", 1);
+        let old = doc.heads();
+        doc.splice_text(0, 9, "oops", 2);
+        assert_eq!(doc.restore(&old, 3).as_deref(), Some("keep this"));
+        assert_eq!(doc.history().last().map(|s| s.end), Some(3));
+    }
+
+    #[test]
+    fn blame_splits_runs_by_author() {
+        let mut a = Doc::new();
+        a.splice_text(0, 0, "hello ", 1);
+        let mut b = Doc::load_from_bytes(&a.save()).unwrap();
+        let change = b.splice_text(6, 0, "world", 2);
+        a.merge_changes(&change);
+        let runs = a.blame();
+        assert_eq!(runs.len(), 2);
+        assert_eq!(runs[0].text, "hello ");
+        assert_eq!(runs[1].text, "world");
+        assert_ne!(runs[0].actor, runs[1].actor);
+        assert_eq!(a.actors_since(&[]).len(), 2);
+    }
+}
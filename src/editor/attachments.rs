@@ -0,0 +1,172 @@
+use dioxus::prelude::*;
+use wasm_bindgen::JsCast;
+use reality::protocol::Attachment;
+use super::history::format_time;
+
+async fn fetch_attachments(id: &str) -> Option<Vec<Attachment>> {
+    let resp = gloo_net::http::Request::get(&format!("/api/doc/{id}/attachments"))
+        .send().await.ok()?;
+    if !resp.ok() { return None; }
+    resp.json().await.ok()
+}
+
+pub async fn upload_attachment(id: &str, file: &web_sys::File) -> Result<Attachment, String> {
+    let name = js_sys::encode_uri_component(&file.name());
+    let resp = gloo_net::http::Request::post(&format!("/api/doc/{id}/attachments?name={name}"))
+        .header("Content-Type", "application/octet-stream")
+        .body(file.clone()).map_err(|e| e.to_string())?
+        .send().await.map_err(|e| e.to_string())?;
+    if !resp.ok() {
+        return Err(resp.text().await.unwrap_or_else(|_| "couldn't upload that file".to_string()));
+    }
+    resp.json().await.map_err(|e| e.to_string())
+}
+
+async fn delete_attachment(id: &str, file: &str) -> bool {
+    gloo_net::http::Request::delete(&format!("/api/doc/{id}/attachments/{file}"))
+        .send().await
+        .map(|r| r.ok())
+        .unwrap_or(false)
+}
+
+// what's been picked in the file input, which is emptied so the same file can be picked again
+fn picked_files() -> Vec<web_sys::File> {
+    let Some(input) = web_sys::window()
+        .and_then(|w| w.document())
+        .and_then(|d| d.get_element_by_id("attachment-input"))
+        .and_then(|e| e.dyn_into::<web_sys::HtmlInputElement>().ok())
+    else {
+        return Vec::new();
+    };
+    let files = input.files().map(|f| (0..f.length()).filter_map(|i| f.get(i)).collect()).unwrap_or_default();
+    input.set_value("");
+    files
+}
+
+fn human_size(bytes: usize) -> String {
+    match bytes {
+        b if b >= 1024 * 1024 => format!("{:.1} MB", b as f64 / (1024.0 * 1024.0)),
+        b if b >= 1024        => format!("{} KB", b / 1024),
+        b                     => format!("{b} bytes"),
+    }
+}
+
+#[derive(Props, Clone, PartialEq)]
+pub struct AttachmentsPanelProps {
+    pub id:       String,
+    pub can_edit: bool,
+    // each upload from here gets linked from the doc, an unlinked file gets cleaned up
+    pub on_uploaded: EventHandler<Attachment>,
+}
+
+// files kept with the doc, plus the export that bundles them with the text
+#[component]
+pub fn AttachmentsPanel(props: AttachmentsPanelProps) -> Element {
+    let mut attachments = use_signal(Vec::<Attachment>::new);
+    let mut status      = use_signal(|| "Loading attachments...".to_string());
+
+    let reload = {
+        let id = props.id.clone();
+        move || {
+            let id = id.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                match fetch_attachments(&id).await {
+                    Some(list) => { attachments.set(list); status.set(String::new()); }
+                    None       => status.set("Couldn't load attachments".to_string()),
+                }
+            });
+        }
+    };
+    use_effect({
+        let reload = reload.clone();
+        move || reload()
+    });
+
+    let on_picked = {
+        let id = props.id.clone();
+        let on_uploaded = props.on_uploaded;
+        move |_| {
+            for file in picked_files() {
+                let id = id.clone();
+                let reload = reload.clone();
+                status.set(format!("Uploading {}...", file.name()));
+                wasm_bindgen_futures::spawn_local(async move {
+                    match upload_attachment(&id, &file).await {
+                        Ok(attachment) => {
+                            on_uploaded.call(attachment);
+                            reload();
+                        }
+                        Err(message) => status.set(message),
+                    }
+                });
+            }
+        }
+    };
+
+    rsx! {
+        div { style: "flex:1;display:flex;flex-direction:column;overflow:hidden;font-family:sans-serif;",
+            div { style: "display:flex;align-items:center;gap:0.75rem;padding:0.5rem 1rem;background:#2a2a3e;color:white;border-bottom:1px solid #3a3a5e;font-size:0.85rem;",
+                span { style: "flex:1;opacity:0.8;", "Files kept with this doc. Ones the text no longer links to are removed after a day." }
+                if props.can_edit {
+                    label {
+                        style: "padding:0.3rem 0.7rem;background:#3a3a5e;border-radius:4px;cursor:pointer;",
+                        "📎 Attach files"
+                        input {
+                            id: "attachment-input",
+                            r#type: "file",
+                            multiple: true,
+                            style: "display:none;",
+                            onchange: on_picked,
+                        }
+                    }
+                }
+                a {
+                    style: "padding:0.3rem 0.7rem;background:#3a3a5e;color:white;border-radius:4px;text-decoration:none;",
+                    href: "/api/doc/{props.id}/export",
+                    title: "The text with every attachment and image, as a .tar",
+                    "⬇ Export"
+                }
+            }
+            if !status.read().is_empty() {
+                div { style: "padding:0.5rem 1rem;font-size:0.85rem;opacity:0.7;", "{status}" }
+            }
+            div { style: "flex:1;overflow:auto;padding:0.5rem 1rem;",
+                if attachments.read().is_empty() && status.read().is_empty() {
+                    div { style: "opacity:0.6;font-size:0.9rem;", "Nothing attached yet, drop a file on the editor or use 📎 Attach files." }
+                }
+                for attachment in attachments.read().iter().cloned() {
+                    div { key: "{attachment.id}", style: "display:flex;align-items:center;gap:0.75rem;padding:0.4rem 0;border-bottom:1px solid #eee;font-size:0.9rem;",
+                        a { style: "flex:1;", href: attachment.url(&props.id), "{attachment.name}" }
+                        if attachment.unreferenced_since.is_some() {
+                            span { style: "color:#7a5a00;font-size:0.8rem;", title: "Link to it from the doc to keep it", "not linked" }
+                        }
+                        span { style: "opacity:0.6;font-size:0.8rem;", "{human_size(attachment.size)}" }
+                        span { style: "opacity:0.6;font-size:0.8rem;", "{format_time(attachment.created)}" }
+                        if props.can_edit {
+                            button {
+                                style: "border:none;background:none;cursor:pointer;",
+                                title: "Delete attachment",
+                                onclick: {
+                                    let id = props.id.clone();
+                                    let file = attachment.id.clone();
+                                    move |_| {
+                                        let id = id.clone();
+                                        let file = file.clone();
+                                        wasm_bindgen_futures::spawn_local(async move {
+                                            if delete_attachment(&id, &file).await {
+                                                attachments.write().retain(|a| a.id != file);
+                                            } else {
+                                                status.set("Couldn't delete that attachment".to_string());
+                                            }
+                                        });
+                                    }
+                                },
+                                "✕"
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
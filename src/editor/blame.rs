@@ -0,0 +1,94 @@
+use dioxus::prelude::*;
+use serde::Deserialize;
+use super::history::format_time;
+
+// matches the server's api::BlameSpan
+#[derive(Deserialize, Clone, PartialEq)]
+pub struct BlameSpan {
+    pub text:  String,
+    pub actor: String,
+    pub name:  String,
+    pub time:  i64,
+}
+
+async fn fetch_blame(id: &str) -> Option<Vec<BlameSpan>> {
+    let resp = gloo_net::http::Request::get(&format!("/api/doc/{id}/blame"))
+        .send().await.ok()?;
+    if !resp.ok() { return None; }
+    resp.json().await.ok()
+}
+
+// people with no name get grouped by actor so they still get told apart
+fn author_label(span: &BlameSpan) -> String {
+    if span.name.is_empty() {
+        let short: String = span.actor.chars().take(6).collect();
+        format!("Unknown ({short})")
+    } else {
+        span.name.clone()
+    }
+}
+
+// same name always gets the same soft color
+fn author_color(label: &str) -> String {
+    let hue = label.bytes().fold(7u32, |h, b| h.wrapping_mul(31).wrapping_add(b as u32)) % 360;
+    format!("hsl({hue}, 70%, 85%)")
+}
+
+#[derive(Props, Clone, PartialEq)]
+pub struct BlamePanelProps {
+    pub id: String,
+}
+
+// whole doc colored by who wrote it, hover a run for the name and time
+#[component]
+pub fn BlamePanel(props: BlamePanelProps) -> Element {
+    let mut spans  = use_signal(Vec::<BlameSpan>::new);
+    let mut status = use_signal(|| "Loading authors...".to_string());
+
+    use_effect({
+        let id = props.id.clone();
+        move || {
+            let id = id.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                match fetch_blame(&id).await {
+                    Some(list) => { spans.set(list); status.set(String::new()); }
+                    None       => status.set("Couldn't load authors".to_string()),
+                }
+            });
+        }
+    });
+
+    let mut authors: Vec<String> = Vec::new();
+    for span in spans.read().iter() {
+        let label = author_label(span);
+        if !authors.contains(&label) {
+            authors.push(label);
+        }
+    }
+
+    rsx! {
+        div { style: "flex:1;display:flex;flex-direction:column;overflow:hidden;font-family:sans-serif;",
+            div { style: "display:flex;align-items:center;gap:0.5rem;flex-wrap:wrap;padding:0.5rem 1rem;background:#2a2a3e;color:white;border-bottom:1px solid #3a3a5e;font-size:0.8rem;",
+                span { style: "opacity:0.8;", "Authors:" }
+                for label in authors.iter() {
+                    span {
+                        style: "padding:0.1rem 0.5rem;border-radius:3px;color:#222;background:{author_color(label)};",
+                        "{label}"
+                    }
+                }
+            }
+            if !status.read().is_empty() {
+                div { style: "padding:0.5rem 1rem;font-size:0.85rem;opacity:0.7;", "{status}" }
+            }
+            div { style: "flex:1;overflow:auto;padding:1rem;font-family:'Fira Code',monospace;font-size:14px;line-height:1.6;white-space:pre-wrap;background:#fafafa;",
+                for span in spans.read().iter() {
+                    span {
+                        style: "background:{author_color(&author_label(span))};",
+                        title: "{author_label(span)} · {format_time(span.time)}",
+                        "{span.text}"
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use dioxus::prelude::*;
+use reality::diff::{Hunk, HunkLine, LineKind};
+
+// from is a comma separated list of heads, None diffs against the current text
+pub async fn fetch_diff(id: &str, from: Option<&str>, to: Option<&str>) -> Option<Vec<Hunk>> {
+    let mut params = Vec::new();
+    if let Some(from) = from { params.push(("from", from.to_string())); }
+    if let Some(to)   = to   { params.push(("to", to.to_string())); }
+    let resp = gloo_net::http::Request::get(&format!("/api/doc/{id}/diff"))
+        .query(params)
+        .send().await.ok()?;
+    if !resp.ok() { return None; }
+    resp.json().await.ok()
+}
+
+#[derive(Props, Clone, PartialEq)]
+pub struct DiffViewProps {
+    pub hunks: Vec<Hunk>,
+}
+
+// unified diff, green for added and red for removed, darker on the exact words
+#[component]
+pub fn DiffView(props: DiffViewProps) -> Element {
+    if props.hunks.is_empty() {
+        return rsx! {
+            div { style: "padding:1rem;font-family:sans-serif;opacity:0.7;", "No differences" }
+        };
+    }
+    rsx! {
+        div { style: "flex:1;overflow:auto;font-family:'Fira Code',monospace;font-size:13px;line-height:1.5;background:#fafafa;",
+            for hunk in props.hunks.iter() {
+                div { style: "padding:0.2rem 1rem;background:#e8e8f0;color:#555;",
+                    "@@ -{hunk.old_start},{hunk.old_len} +{hunk.new_start},{hunk.new_len} @@"
+                }
+                for line in hunk.lines.iter() {
+                    DiffLine { line: line.clone() }
+                }
+            }
+        }
+    }
+}
+
+#[component]
+fn DiffLine(line: HunkLine) -> Element {
+    let (sign, bg, strong) = match line.kind {
+        LineKind::Equal  => (" ", "transparent", "transparent"),
+        LineKind::Insert => ("+", "#e6ffec", "#abf2bc"),
+        LineKind::Delete => ("-", "#ffebe9", "#ffb3ad"),
+    };
+    rsx! {
+        div { style: "padding:0 1rem;white-space:pre-wrap;background:{bg};",
+            span { style: "opacity:0.5;user-select:none;", "{sign} " }
+            for seg in line.segments.iter() {
+                span {
+                    style: if seg.changed { "background:{strong};" } else { "" },
+                    "{seg.text}"
+                }
+            }
+        }
+    }
+}
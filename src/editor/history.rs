@@ -0,0 +1,272 @@
+use dioxus::prelude::*;
+use serde::Deserialize;
+use reality::diff::Hunk;
+use reality::doc::Session;
+use super::diff_view::{fetch_diff, DiffView};
+
+// matches the server's meta::Tag
+#[derive(Deserialize, Clone, PartialEq)]
+pub struct Tag {
+    pub name:    String,
+    pub heads:   Vec<String>,
+    pub created: i64,
+}
+
+async fn fetch_history(id: &str) -> Option<Vec<Session>> {
+    gloo_net::http::Request::get(&format!("/api/doc/{id}/history"))
+        .send().await.ok()?
+        .json().await.ok()
+}
+
+async fn fetch_text_at(id: &str, heads: &[String]) -> Option<String> {
+    let resp = gloo_net::http::Request::get(&format!("/api/doc/{id}/at"))
+        .query([("heads", heads.join(","))])
+        .send().await.ok()?;
+    if !resp.ok() { return None; }
+    resp.text().await.ok()
+}
+
+async fn fetch_tags(id: &str) -> Option<Vec<Tag>> {
+    gloo_net::http::Request::get(&format!("/api/doc/{id}/tags"))
+        .send().await.ok()?
+        .json().await.ok()
+}
+
+async fn post_tag(id: &str, name: &str, heads: &[String]) -> Option<Tag> {
+    let body = serde_json::json!({ "name": name, "heads": heads });
+    let resp = gloo_net::http::Request::post(&format!("/api/doc/{id}/tags"))
+        .json(&body).ok()?
+        .send().await.ok()?;
+    if !resp.ok() { return None; }
+    resp.json().await.ok()
+}
+
+async fn delete_tag(id: &str, name: &str) -> bool {
+    let url = format!("/api/doc/{id}/tags/{}", js_sys::encode_uri_component(name));
+    matches!(gloo_net::http::Request::delete(&url).send().await, Ok(resp) if resp.ok())
+}
+
+fn tag_export_url(id: &str, name: &str) -> String {
+    format!("/api/doc/{id}/tags/{}/text?download=true", js_sys::encode_uri_component(name))
+}
+
+// asks the server to roll the doc back, it comes back to us over the socket like any other edit
+async fn post_restore(id: &str, heads: &[String]) -> bool {
+    let body = serde_json::json!({ "heads": heads });
+    let Ok(req) = gloo_net::http::Request::post(&format!("/api/doc/{id}/restore")).json(&body) else {
+        return false;
+    };
+    matches!(req.send().await, Ok(resp) if resp.ok())
+}
+
+fn session_label(s: &Session) -> String {
+    let who: String = s.actor.chars().take(8).collect();
+    format!("{} · {} · {} change(s)", format_time(s.end), who, s.changes)
+}
+
+pub fn format_time(secs: i64) -> String {
+    if secs == 0 { return "unknown time".to_string(); }
+    let date = js_sys::Date::new(&wasm_bindgen::JsValue::from_f64(secs as f64 * 1000.0));
+    date.to_locale_string("default", &wasm_bindgen::JsValue::UNDEFINED).into()
+}
+
+#[derive(Props, Clone, PartialEq)]
+pub struct HistoryPanelProps {
+    pub id: String,
+    pub on_restored: EventHandler<()>,
+}
+
+// timeline slider over the doc's sessions plus named tags, shows the old text read only
+#[component]
+pub fn HistoryPanel(props: HistoryPanelProps) -> Element {
+    let mut sessions = use_signal(Vec::<Session>::new);
+    let mut tags     = use_signal(Vec::<Tag>::new);
+    let mut selected = use_signal(|| 0usize);
+    let mut shown    = use_signal(Vec::<String>::new);
+    let mut label    = use_signal(String::new);
+    let mut old_text = use_signal(String::new);
+    let mut status   = use_signal(|| "Loading history...".to_string());
+    let mut compare  = use_signal(|| false);
+    let mut hunks    = use_signal(Vec::<Hunk>::new);
+
+    // heads are what actually pick the version, the slider and the tag list both end up here
+    let load_heads = {
+        let id = props.id.clone();
+        move |heads: Vec<String>, text: String| {
+            shown.set(heads.clone());
+            label.set(text);
+            let id = id.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                match fetch_text_at(&id, &heads).await {
+                    Some(text) => { old_text.set(text); status.set(String::new()); }
+                    None       => status.set("Couldn't load that version".to_string()),
+                }
+                // diff is always old version -> current so it reads as "what changed since"
+                if let Some(list) = fetch_diff(&id, Some(&heads.join(",")), None).await {
+                    hunks.set(list);
+                }
+            });
+        }
+    };
+
+    let load_version = {
+        let mut load_heads = load_heads.clone();
+        move |idx: usize| {
+            let Some(session) = sessions.read().get(idx).cloned() else { return; };
+            selected.set(idx);
+            load_heads(session.heads.clone(), session_label(&session));
+        }
+    };
+
+    use_effect({
+        let id = props.id.clone();
+        let load_version = load_version.clone();
+        move || {
+            let id = id.clone();
+            let mut load_version = load_version.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                match fetch_history(&id).await {
+                    Some(list) if !list.is_empty() => {
+                        let last = list.len() - 1;
+                        sessions.set(list);
+                        load_version(last);
+                    }
+                    Some(_) => status.set("No history yet".to_string()),
+                    None    => status.set("Couldn't load history".to_string()),
+                }
+                if let Some(list) = fetch_tags(&id).await {
+                    tags.set(list);
+                }
+            });
+        }
+    });
+
+    let count     = sessions.read().len();
+    let is_latest = sessions.read().last().is_none_or(|s| s.heads == *shown.read());
+
+    rsx! {
+        div { style: "flex:1;display:flex;flex-direction:column;overflow:hidden;font-family:sans-serif;",
+            div { style: "display:flex;align-items:center;gap:1rem;padding:0.5rem 1rem;background:#2a2a3e;color:white;border-bottom:1px solid #3a3a5e;",
+                input {
+                    r#type: "range",
+                    style: "flex:1;",
+                    min: "0",
+                    max: "{count.saturating_sub(1)}",
+                    value: "{selected}",
+                    disabled: count == 0,
+                    oninput: {
+                        let mut load_version = load_version.clone();
+                        move |e: Event<FormData>| {
+                            if let Ok(idx) = e.value().parse::<usize>() {
+                                load_version(idx);
+                            }
+                        }
+                    },
+                }
+                span { style: "font-size:0.8rem;opacity:0.8;white-space:nowrap;", "{label}" }
+                button {
+                    style: "padding:0.3rem 0.7rem;background:#3a3a5e;color:white;border:none;border-radius:4px;cursor:pointer;",
+                    onclick: move |_| compare.set(!compare()),
+                    if compare() { "📄 Show text" } else { "± Compare with current" }
+                }
+                button {
+                    style: "padding:0.3rem 0.7rem;background:#3a3a5e;color:white;border:none;border-radius:4px;cursor:pointer;",
+                    disabled: shown.read().is_empty(),
+                    onclick: {
+                        let id = props.id.clone();
+                        move |_| {
+                            let window = web_sys::window().unwrap();
+                            let name = match window.prompt_with_message("Name this version") {
+                                Ok(Some(n)) if !n.trim().is_empty() => n.trim().to_string(),
+                                _ => return,
+                            };
+                            let heads = shown.read().clone();
+                            let id = id.clone();
+                            wasm_bindgen_futures::spawn_local(async move {
+                                match post_tag(&id, &name, &heads).await {
+                                    Some(tag) => tags.write().push(tag),
+                                    None      => status.set("Couldn't save that tag".to_string()),
+                                }
+                            });
+                        }
+                    },
+                    "🏷 Tag"
+                }
+                button {
+                    style: "padding:0.3rem 0.7rem;background:#3a3a5e;color:white;border:none;border-radius:4px;cursor:pointer;",
+                    disabled: is_latest,
+                    onclick: {
+                        let id = props.id.clone();
+                        move |_| {
+                            let heads = shown.read().clone();
+                            let id = id.clone();
+                            let on_restored = props.on_restored;
+                            wasm_bindgen_futures::spawn_local(async move {
+                                if post_restore(&id, &heads).await {
+                                    on_restored.call(());
+                                } else {
+                                    status.set("Restore failed".to_string());
+                                }
+                            });
+                        }
+                    },
+                    "↺ Restore this version"
+                }
+            }
+            if !status.read().is_empty() {
+                div { style: "padding:0.5rem 1rem;font-size:0.85rem;opacity:0.7;", "{status}" }
+            }
+            div { style: "flex:1;display:flex;overflow:hidden;",
+                if compare() {
+                    DiffView { hunks: hunks() }
+                } else {
+                    textarea {
+                        style: "flex:1;padding:1rem;font-family:'Fira Code',monospace;font-size:14px;line-height:1.6;border:none;resize:none;outline:none;background:#f0f0f4;width:100%;box-sizing:border-box;",
+                        readonly: true,
+                        value: "{old_text}",
+                    }
+                }
+                // named versions, click to jump, arrow to download that version as .md
+                div { style: "width:220px;flex-shrink:0;overflow:auto;border-left:1px solid #ddd;padding:0.5rem;font-size:0.85rem;",
+                    div { style: "font-weight:bold;margin-bottom:0.5rem;", "Tags" }
+                    if tags.read().is_empty() {
+                        div { style: "opacity:0.6;", "No tags yet" }
+                    }
+                    for tag in tags.read().iter().cloned() {
+                        div { key: "{tag.name}", style: "display:flex;align-items:center;gap:0.25rem;padding:0.2rem 0;",
+                            span {
+                                style: "flex:1;cursor:pointer;text-decoration:underline;",
+                                title: "{format_time(tag.created)}",
+                                onclick: {
+                                    let mut load_heads = load_heads.clone();
+                                    let tag = tag.clone();
+                                    move |_| load_heads(tag.heads.clone(), format!("🏷 {}", tag.name))
+                                },
+                                "{tag.name}"
+                            }
+                            a { href: tag_export_url(&props.id, &tag.name), title: "Download", "⬇" }
+                            button {
+                                style: "border:none;background:none;cursor:pointer;",
+                                title: "Delete tag",
+                                onclick: {
+                                    let id = props.id.clone();
+                                    let name = tag.name.clone();
+                                    move |_| {
+                                        let id = id.clone();
+                                        let name = name.clone();
+                                        wasm_bindgen_futures::spawn_local(async move {
+                                            if delete_tag(&id, &name).await {
+                                                tags.write().retain(|t| t.name != name);
+                                            }
+                                        });
+                                    }
+                                },
+                                "✕"
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
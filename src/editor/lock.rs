@@ -0,0 +1,78 @@
+use dioxus::prelude::*;
+
+// swaps the password for a short lived pass cookie, which the socket upgrade then checks
+async fn unlock(id: &str, password: &str) -> Result<(), String> {
+    let body = serde_json::json!({ "password": password });
+    let resp = gloo_net::http::Request::post(&format!("/api/doc/{id}/unlock"))
+        .json(&body).map_err(|e| e.to_string())?
+        .send().await.map_err(|e| e.to_string())?;
+    if !resp.ok() {
+        return Err(resp.text().await.unwrap_or_else(|_| "couldn't unlock".to_string()));
+    }
+    Ok(())
+}
+
+// None takes the password off
+pub async fn set_doc_password(id: &str, password: Option<&str>) -> Result<(), String> {
+    let body = serde_json::json!({ "password": password });
+    let resp = gloo_net::http::Request::put(&format!("/api/doc/{id}/password"))
+        .json(&body).map_err(|e| e.to_string())?
+        .send().await.map_err(|e| e.to_string())?;
+    if !resp.ok() {
+        return Err(resp.text().await.unwrap_or_else(|_| "couldn't change the password".to_string()));
+    }
+    Ok(())
+}
+
+#[component]
+pub fn PasswordPrompt(id: String, on_unlocked: EventHandler<()>) -> Element {
+    let mut password = use_signal(String::new);
+    let mut error    = use_signal(String::new);
+    let mut busy     = use_signal(|| false);
+
+    let mut submit = move |id: String| {
+        if busy() { return; }
+        busy.set(true);
+        let pw = password.read().clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            let result = unlock(&id, &pw).await;
+            // unlocking unmounts this screen, so we're done with our own signals first
+            busy.set(false);
+            match result {
+                Ok(()) => on_unlocked.call(()),
+                Err(e) => { error.set(e); password.set(String::new()); }
+            }
+        });
+    };
+
+    rsx! {
+        div { style: "display:flex;flex-direction:column;align-items:center;justify-content:center;height:100vh;font-family:sans-serif;gap:0.75rem;",
+            h2 { "🔒 This doc is password protected" }
+            form {
+                style: "display:flex;gap:0.5rem;",
+                onsubmit: {
+                    let id = id.clone();
+                    move |e: Event<FormData>| { e.prevent_default(); submit(id.clone()); }
+                },
+                input {
+                    r#type: "password",
+                    style: "padding:0.4rem 0.6rem;border:1px solid #ccc;border-radius:4px;",
+                    placeholder: "Password",
+                    autofocus: true,
+                    value: "{password}",
+                    oninput: move |e| password.set(e.value()),
+                }
+                button {
+                    r#type: "submit",
+                    style: "padding:0.4rem 0.9rem;background:#1a1a2e;color:white;border:none;border-radius:4px;cursor:pointer;",
+                    disabled: busy(),
+                    "Open"
+                }
+            }
+            if !error.read().is_empty() {
+                p { style: "margin:0;font-size:0.85rem;color:#c00;", "{error}" }
+            }
+            a { href: "/", style: "font-size:0.85rem;", "Back to Reality" }
+        }
+    }
+}
@@ -1,19 +1,54 @@
 #![allow(non_snake_case)]
-pub mod crdt;
+pub mod attachments;
+pub mod blame;
+pub mod diff_view;
+pub mod history;
+pub mod lock;
+pub mod outline;
+pub mod sharing;
 pub mod toolbar;
+pub mod upload;
 
 use dioxus::prelude::*;
 use gloo_net::websocket::{futures::WebSocket, Message};
 use futures_util::{SinkExt, StreamExt};
+use crate::account::{fetch_me, Me};
+use reality::diff;
+use reality::doc::Doc;
+use reality::highlight::{theme_css, DEFAULT_THEME, THEMES};
+use reality::markdown::{headings, image_markdown, link_markdown, render_markdown_with, toggle_task, Heading, RenderOptions, MARKDOWN_STYLE};
+use reality::protocol::{ClientMsg, Role, ServerMsg};
+use reality::table::{edit_table, NEW_TABLE};
+use attachments::{upload_attachment, AttachmentsPanel};
+use blame::BlamePanel;
+use history::HistoryPanel;
+use lock::PasswordPrompt;
+use outline::OutlinePanel;
+use sharing::{fetch_sharing, SharingDialog};
 use toolbar::{Toolbar, ToolbarAction};
+use upload::{alt_text, dragging_files, dropped_files, is_image, pasted_files, upload_image};
 
-fn get_ws_url(id: &str) -> String {
+// read only pages connect with their view token instead of the doc id
+fn get_ws_url(id: &str, read_only: bool) -> String {
     let window = web_sys::window().unwrap();
     let location = window.location();
     let host = location.host().unwrap();
     let protocol = location.protocol().unwrap();
     let ws_protocol = if protocol == "https:" { "wss:" } else { "ws:" };
-    format!("{}//{}/ws/{}", ws_protocol, host, id)
+    let route = if read_only { "ws-view" } else { "ws" };
+    format!("{}//{}/{}/{}", ws_protocol, host, route, id)
+}
+
+async fn fetch_view_token(id: &str) -> Option<String> {
+    let v: serde_json::Value = gloo_net::http::Request::get(&format!("/api/doc/{id}/view-token"))
+        .send().await.ok()?
+        .json().await.ok()?;
+    Some(v.get("token")?.as_str()?.to_string())
+}
+
+async fn copy_to_clipboard(text: &str) {
+    let cb = web_sys::window().unwrap().navigator().clipboard();
+    let _ = wasm_bindgen_futures::JsFuture::from(cb.write_text(text)).await;
 }
 
 fn get_textarea() -> Option<web_sys::HtmlTextAreaElement> {
@@ -44,13 +79,54 @@ fn set_cursor(start: u32, end: u32) {
     }
 }
 
+// the textarea counts utf-16 units where the markdown side counts chars, so anything past an
+// emoji is off by one per emoji unless it's converted
+fn utf16_offset(text: &str, chars: usize) -> u32 {
+    text.chars().take(chars).map(char::len_utf16).sum::<usize>() as u32
+}
+
+fn char_offset(text: &str, utf16: u32) -> usize {
+    let mut units = 0;
+    text.chars().take_while(|c| { units += c.len_utf16(); units <= utf16 as usize }).count()
+}
+
+// where the caret is, in chars
+fn caret_in(text: &str) -> usize {
+    char_offset(text, get_cursor().0)
+}
+
+// the #section out of /doc/:id#section, as the heading ids spell it
+fn location_anchor() -> Option<String> {
+    let hash = web_sys::window()?.location().hash().ok()?;
+    let hash = hash.strip_prefix('#').filter(|h| !h.is_empty())?;
+    js_sys::decode_uri_component(hash).ok().map(String::from)
+}
+
+// the preview has the id on the heading itself, the textarea only has the text so the
+// cursor goes to the heading and focusing scrolls it into view
+fn scroll_to_anchor(anchor: &str, text: &str) {
+    let document = web_sys::window().and_then(|w| w.document());
+    if let Some(el) = document.and_then(|d| d.get_element_by_id(anchor)) {
+        if el.closest(".markdown").ok().flatten().is_some() {
+            el.scroll_into_view();
+            return;
+        }
+    }
+    let Some(ta) = get_textarea() else { return };
+    let Some(heading) = headings(text).into_iter().find(|h| h.id == anchor) else { return };
+    let at = utf16_offset(text, heading.at);
+    set_cursor(at, at);
+    let _ = ta.blur();
+    let _ = ta.focus();
+}
+
 fn apply_remote_patch(old_text: &str, new_text: &str) {
     let ta = match get_textarea() {
         Some(t) => t,
         None    => return,
     };
 
-    let (splice_at, del, ins) = crdt::diff(old_text, new_text);
+    let (splice_at, del, ins) = diff::splice(old_text, new_text);
     let ins_len = ins.chars().count();
     let (cur_start, cur_end) = get_cursor();
     ta.set_value(new_text);
@@ -59,6 +135,45 @@ fn apply_remote_patch(old_text: &str, new_text: &str) {
     set_cursor(new_start, new_end);
 }
 
+// the name other people see on your edits, kept in localStorage so you only type it once
+const NAME_KEY: &str = "reality-name";
+
+fn load_display_name() -> String {
+    web_sys::window()
+        .and_then(|w| w.local_storage().ok().flatten())
+        .and_then(|s| s.get_item(NAME_KEY).ok().flatten())
+        .unwrap_or_default()
+}
+
+fn save_display_name(name: &str) {
+    if let Some(storage) = web_sys::window().and_then(|w| w.local_storage().ok().flatten()) {
+        let _ = storage.set_item(NAME_KEY, name);
+    }
+}
+
+// colours for code blocks in the preview, also remembered per browser
+const THEME_KEY: &str = "reality-code-theme";
+
+fn load_code_theme() -> String {
+    web_sys::window()
+        .and_then(|w| w.local_storage().ok().flatten())
+        .and_then(|s| s.get_item(THEME_KEY).ok().flatten())
+        .filter(|t| THEMES.contains(&t.as_str()))
+        .unwrap_or_else(|| DEFAULT_THEME.to_string())
+}
+
+fn save_code_theme(theme: &str) {
+    if let Some(storage) = web_sys::window().and_then(|w| w.local_storage().ok().flatten()) {
+        let _ = storage.set_item(THEME_KEY, theme);
+    }
+}
+
+// None when they cancel, an empty answer still makes a plain block
+fn ask_code_language() -> Option<String> {
+    let answer = web_sys::window()?.prompt_with_message("Language for the code block (e.g. rust, python, bash), or leave empty").ok()??;
+    Some(answer.trim().to_string())
+}
+
 fn generate_client_id() -> String {
     let a = (js_sys::Math::random() * 0xffffffff_u32 as f64) as u64;
     let b = (js_sys::Math::random() * 0xffffffff_u32 as f64) as u64;
@@ -67,25 +182,104 @@ fn generate_client_id() -> String {
 
 #[component]
 pub fn Editor(id: String) -> Element {
-    let mut content   = use_signal(|| String::new());
-    let mut preview   = use_signal(|| false);
-    let mut last_text = use_signal(|| String::new());
-    let client_id     = use_signal(|| generate_client_id());
-    let mut doc = use_signal(|| crdt::Doc::new());
+    rsx! { DocPage { id, read_only: false } }
+}
+
+// /view/:token, same doc but preview only and the server ignores anything we send
+#[component]
+pub fn View(token: String) -> Element {
+    rsx! { DocPage { id: token, read_only: true } }
+}
+
+#[component]
+fn DocPage(id: String, read_only: bool) -> Element {
+    let mut content   = use_signal(String::new);
+    let mut preview   = use_signal(move || read_only);
+    let mut history   = use_signal(|| false);
+    let mut blame     = use_signal(|| false);
+    let mut files     = use_signal(|| false);
+    let mut outline   = use_signal(|| false);
+    let mut sharing   = use_signal(|| false);
+    let mut my_role   = use_signal(|| None::<Role>);
+    let mut no_access = use_signal(|| false);
+    let mut locked    = use_signal(|| false);
+    // the last thing the server turned down, shown until dismissed
+    let mut refused   = use_signal(|| None::<String>);
+    // set while the server is holding our edits back, cleared once one of them comes through
+    let mut throttled = use_signal(|| None::<String>);
+    // images and attachments on their way to the server
+    let mut uploading = use_signal(|| 0usize);
+    // the socket waits for this so a password protected doc gets unlocked first
+    let mut ready     = use_signal(move || read_only);
+    let mut my_name   = use_signal(load_display_name);
+    let mut code_theme = use_signal(load_code_theme);
+    let theme_style   = use_memo(move || theme_css(&code_theme()));
+    let mut me        = use_signal(|| None::<Me>);
+    let mut last_text = use_signal(String::new);
+    // a /doc/:id#section link, scrolled to once the text is in
+    let mut anchor    = use_signal(location_anchor);
+    // where the caret was last seen, for the outline to show which section it's in
+    let mut caret     = use_signal(|| 0usize);
+    let doc_headings  = use_memo(move || headings(&content.read()));
+    let client_id     = use_signal(generate_client_id);
+    let mut doc = use_signal(Doc::new);
 
     let ws_tx: Signal<Option<futures_channel::mpsc::UnboundedSender<String>>> =
         use_signal(|| None);
 
+    // signed in people edit under their account name, the server enforces that anyway
+    use_effect(move || {
+        wasm_bindgen_futures::spawn_local(async move {
+            if let Some(user) = fetch_me().await {
+                my_name.set(user.username.clone());
+                me.set(Some(user));
+            }
+        });
+    });
+
+    // what we're allowed to do here, view links are always viewer so there's nothing to ask
+    let load_role = {
+        let id = id.clone();
+        move || {
+            let id = id.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                match fetch_sharing(&id).await {
+                    Ok(s)    => { my_role.set(s.my_role); locked.set(false); }
+                    Err(401) => { locked.set(true); return; }
+                    Err(403) => { no_access.set(true); return; }
+                    Err(_)   => {}
+                }
+                if !*ready.peek() {
+                    ready.set(true);
+                }
+            });
+        }
+    };
+    use_effect({
+        let load_role = load_role.clone();
+        move || if !read_only { load_role() }
+    });
+    let can_edit = !read_only && my_role().is_some_and(Role::can_edit);
+
+    use_effect(move || {
+        let text = content.read();
+        if text.is_empty() { return; }
+        let Some(id) = anchor.peek().clone() else { return };
+        scroll_to_anchor(&id, &text);
+        anchor.set(None);
+    });
+
     use_effect({
         let id        = id.clone();
-        let mut ws_tx = ws_tx.clone();
+        let mut ws_tx = ws_tx;
 
         move || {
+            if !ready() { return; }
             let id        = id.clone();
             let client_id = client_id.read().clone();
 
             wasm_bindgen_futures::spawn_local(async move {
-                let ws = match WebSocket::open(&get_ws_url(&id)) {
+                let ws = match WebSocket::open(&get_ws_url(&id, read_only)) {
                     Ok(ws) => ws,
                     Err(e) => { eprintln!("WS error: {:?}", e); return; }
                 };
@@ -110,13 +304,23 @@ pub fn Editor(id: String) -> Element {
                             },
                         };
                     
-                        let (text, sender_id, full_doc_bytes) = match decode_payload(json.as_bytes()) {
-                            Some(v) => v,
-                            None    => continue,
+                        let ServerMsg { sender_id, text, full_doc, error } = match serde_json::from_str(&json) {
+                            Ok(v)  => v,
+                            Err(_) => continue,
                         };
-                        
+
+                        // a resync with the server's copy follows straight after
+                        if sender_id == "error" {
+                            refused.set(error);
+                            continue;
+                        }
+                        if sender_id == "throttled" {
+                            throttled.set(error);
+                            continue;
+                        }
+
                         if sender_id == "server" {
-                            if let Some(loaded) = crdt::Doc::load_from_bytes(&full_doc_bytes) {
+                            if let Some(loaded) = Doc::load_from_bytes(&full_doc) {
                                 *doc.write() = loaded;
                             }
                             let current_text = doc.read().get_text();
@@ -127,11 +331,18 @@ pub fn Editor(id: String) -> Element {
                             content.set(current_text);
                             continue;
                         }
-                        if sender_id != client_id {
+                        if sender_id == client_id {
+                            if throttled.peek().is_some() {
+                                throttled.set(None);
+                            }
+                        } else {
                             let old_text = last_text.read().clone();
-                            let merged_text = doc.write().merge_from_bytes(&full_doc_bytes);
+                            let merged_text = doc.write().merge_changes(&full_doc);
                             let new_text = merged_text.unwrap_or(text);
                             apply_remote_patch(&old_text, &new_text);
+                            if get_textarea().is_some() {
+                                caret.set(caret_in(&new_text));
+                            }
                             last_text.set(new_text.clone());
                             content.set(new_text);
                         }
@@ -142,22 +353,21 @@ pub fn Editor(id: String) -> Element {
     });
 
     let mut send_patch = move |old: &str, new: &str| {
-        let (insert_at, delete_count, inserted_text) = crdt::diff(old, new);
+        let (insert_at, delete_count, inserted_text) = diff::splice(old, new);
         if delete_count == 0 && inserted_text.is_empty() { return; }
-    
-        let change_bytes = {
-            let mut d = doc.write();
-            d.splice_text(insert_at, delete_count, &inserted_text);
-            d.save_changes()
+
+        // stamp the change so the history view can group edits by time
+        let now = (js_sys::Date::now() / 1000.0) as i64;
+        let changes = doc.write().splice_text(insert_at, delete_count, &inserted_text, now);
+
+        let msg = ClientMsg {
+            client_id: client_id.read().clone(),
+            changes,
+            name: Some(my_name.read().clone()),
         };
-    
-        let msg = serde_json::json!({
-            "client_id": &*client_id.read(),
-            "changes": change_bytes,
-        });
-    
+
         if let Some(tx) = ws_tx.read().as_ref() {
-            let _ = tx.unbounded_send(msg.to_string());
+            let _ = tx.unbounded_send(serde_json::to_string(&msg).unwrap());
         }
     };
 
@@ -166,53 +376,224 @@ pub fn Editor(id: String) -> Element {
         let old_text = last_text.read().clone();
         send_patch(&old_text, &new_text);
         last_text.set(new_text.clone());
+        caret.set(caret_in(&new_text));
+        content.set(new_text);
+    };
+
+    // edits made by us rather than typed, the textarea is updated to match and the cursor put after them.
+    // cursor_after is in chars like the rest of the markdown side
+    let mut replace_text = move |old_text: &str, new_text: String, cursor_after: usize| {
+        if let Some(ta) = get_textarea() {
+            ta.set_value(&new_text);
+        }
+        let at = utf16_offset(&new_text, cursor_after);
+        set_cursor(at, at);
+        caret.set(cursor_after);
+        send_patch(old_text, &new_text);
+        last_text.set(new_text.clone());
         content.set(new_text);
     };
 
     let handle_toolbar = move |action: ToolbarAction| {
+        let action = match action {
+            ToolbarAction::CodeBlock(_) => match ask_code_language() {
+                Some(lang) => ToolbarAction::CodeBlock(lang),
+                None       => return,
+            },
+            action => action,
+        };
         let old_text = content.read().clone();
         let (sel_start, sel_end) = get_cursor();
         let (new_text, cursor_after) = apply_toolbar_action_at_cursor(
-            &old_text, action, sel_start as usize, sel_end as usize,
+            &old_text, action, char_offset(&old_text, sel_start), char_offset(&old_text, sel_end),
         );
+        replace_text(&old_text, new_text, cursor_after);
+    };
 
-        if let Some(ta) = get_textarea() {
-            ta.set_value(&new_text);
+    // markdown put in wherever the cursor is, or on the end when the textarea isn't showing
+    let mut insert_markdown = move |snippet: String| {
+        let old_text = content.read().clone();
+        let (sel_start, sel_end) = match get_textarea() {
+            Some(_) => { let (start, end) = get_cursor(); (char_offset(&old_text, start), char_offset(&old_text, end)) }
+            None    => { let end = old_text.chars().count(); (end, end) }
+        };
+        let (new_text, cursor_after) = insert_at(&old_text, &snippet, sel_start, sel_end);
+        replace_text(&old_text, new_text, cursor_after);
+    };
+
+    // images show up inline, anything else is attached to the doc and linked.
+    // each link goes in wherever the cursor is once its upload finishes
+    let handle_files = {
+        let id = id.clone();
+        move |dropped: Vec<web_sys::File>| {
+            for file in dropped {
+                let id = id.clone();
+                uploading += 1;
+                wasm_bindgen_futures::spawn_local(async move {
+                    let uploaded = if is_image(&file) {
+                        upload_image(&id, &file).await.map(|asset| image_markdown(&alt_text(&file), &asset.url))
+                    } else {
+                        upload_attachment(&id, &file).await.map(|a| link_markdown(&a.name, &a.url(&id)))
+                    };
+                    match uploaded {
+                        Ok(snippet)  => insert_markdown(snippet),
+                        Err(message) => refused.set(Some(message)),
+                    }
+                    uploading -= 1;
+                });
+            }
         }
-        set_cursor(cursor_after as u32, cursor_after as u32);
+    };
+
+    // ticking a box in the preview changes the one character between its brackets
+    let mut handle_preview_click = move |e: Event<MouseData>| {
+        let Some(at) = clicked_task(&e) else { return };
+        let old_text = content.read().clone();
+        let Some(new_text) = toggle_task(&old_text, at) else { return };
         send_patch(&old_text, &new_text);
         last_text.set(new_text.clone());
         content.set(new_text);
     };
 
+    // the textarea gets its caret put on the heading, the preview scrolls to it
+    let jump_to_heading = move |heading: Heading| {
+        scroll_to_anchor(&heading.id, &content.read());
+        caret.set(heading.at);
+    };
+
     let id_display = id.clone();
 
+    if locked() {
+        return rsx! {
+            PasswordPrompt {
+                id: id.clone(),
+                on_unlocked: {
+                    let load_role = load_role.clone();
+                    move |_| load_role()
+                },
+            }
+        };
+    }
+
+    if no_access() {
+        return rsx! {
+            div { style: "display:flex;flex-direction:column;align-items:center;justify-content:center;height:100vh;font-family:sans-serif;gap:0.5rem;",
+                h2 { "You don't have access to this doc" }
+                p { style: "color:#666;", "Ask the owner to share it with you, or sign in with an account that has access." }
+                a { href: "/", "Back to Reality" }
+            }
+        };
+    }
+
     rsx! {
         // this is the site / code for the actual note taking app, not the landing page like landing.rs
         div { style: "display:flex;flex-direction:column;height:100vh;font-family:monospace;",
 
             div { style: "display:flex;align-items:center;padding:0.5rem 1rem;background:#1a1a2e;color:white;gap:1rem;flex-shrink:0;",
                 span { style: "font-weight:bold;font-family:sans-serif;font-size:1.1rem;", "Reality" }
-                span { style: "font-size:0.75rem;opacity:0.6;flex:1;font-family:sans-serif;", "/{id_display}" }
-                button {
-                    style: "padding:0.3rem 0.7rem;background:#3a3a5e;color:white;border:none;border-radius:4px;cursor:pointer;",
-                    // copying the link
-                    onclick: move |_| {
-                        let id = id_display.clone();
-                        wasm_bindgen_futures::spawn_local(async move {
-                            let window = web_sys::window().unwrap();
-                            let origin = window.location().origin().unwrap();
-                            let url = format!("{}/doc/{}", origin, id);
-                            let cb = window.navigator().clipboard();
-                            let _ = wasm_bindgen_futures::JsFuture::from(cb.write_text(&url)).await;
-                        });
-                    },
-                    "Copy Link"
+                if read_only {
+                    span { style: "font-size:0.75rem;opacity:0.6;flex:1;font-family:sans-serif;", "view only" }
+                    button {
+                        style: "padding:0.3rem 0.7rem;background:#3a3a5e;color:white;border:none;border-radius:4px;cursor:pointer;",
+                        onclick: move |_| {
+                            let token = id_display.clone();
+                            wasm_bindgen_futures::spawn_local(async move {
+                                let origin = web_sys::window().unwrap().location().origin().unwrap();
+                                copy_to_clipboard(&format!("{}/view/{}", origin, token)).await;
+                            });
+                        },
+                        "Copy Link"
+                    }
+                } else {
+                    span { style: "font-size:0.75rem;opacity:0.6;flex:1;font-family:sans-serif;", "/{id_display}" }
+                    if let Some(user) = me.read().as_ref() {
+                        span { style: "font-size:0.85rem;font-family:sans-serif;", "👤 {user.username}" }
+                    } else {
+                        input {
+                            style: "padding:0.25rem 0.5rem;background:#2a2a3e;color:white;border:1px solid #3a3a5e;border-radius:4px;width:9rem;",
+                            placeholder: "Your name",
+                            value: "{my_name}",
+                            oninput: move |e| {
+                                save_display_name(&e.value());
+                                my_name.set(e.value());
+                            },
+                        }
+                    }
+                    button {
+                        style: "padding:0.3rem 0.7rem;background:#3a3a5e;color:white;border:none;border-radius:4px;cursor:pointer;",
+                        // copying the link
+                        onclick: {
+                            let id = id_display.clone();
+                            move |_| {
+                                let id = id.clone();
+                                wasm_bindgen_futures::spawn_local(async move {
+                                    let origin = web_sys::window().unwrap().location().origin().unwrap();
+                                    copy_to_clipboard(&format!("{}/doc/{}", origin, id)).await;
+                                });
+                            }
+                        },
+                        "Copy Link"
+                    }
+                    button {
+                        style: "padding:0.3rem 0.7rem;background:#3a3a5e;color:white;border:none;border-radius:4px;cursor:pointer;",
+                        title: "Link that can read but not edit",
+                        onclick: {
+                            let id = id_display.clone();
+                            move |_| {
+                                let id = id.clone();
+                                wasm_bindgen_futures::spawn_local(async move {
+                                    let Some(token) = fetch_view_token(&id).await else { return; };
+                                    let origin = web_sys::window().unwrap().location().origin().unwrap();
+                                    copy_to_clipboard(&format!("{}/view/{}", origin, token)).await;
+                                });
+                            }
+                        },
+                        "Copy View Link"
+                    }
+                    button {
+                        style: "padding:0.3rem 0.7rem;background:#3a3a5e;color:white;border:none;border-radius:4px;cursor:pointer;",
+                        onclick: move |_| preview.set(!preview()),
+                        if preview() { "✏ Edit" } else { "👁 Preview" }
+                    }
+                    button {
+                        style: "padding:0.3rem 0.7rem;background:#3a3a5e;color:white;border:none;border-radius:4px;cursor:pointer;",
+                        onclick: move |_| { blame.set(false); files.set(false); history.set(!history()); },
+                        if history() { "✕ Close history" } else { "🕘 History" }
+                    }
+                    button {
+                        style: "padding:0.3rem 0.7rem;background:#3a3a5e;color:white;border:none;border-radius:4px;cursor:pointer;",
+                        onclick: move |_| { history.set(false); files.set(false); blame.set(!blame()); },
+                        if blame() { "✕ Close authors" } else { "👥 Authors" }
+                    }
+                    button {
+                        style: "padding:0.3rem 0.7rem;background:#3a3a5e;color:white;border:none;border-radius:4px;cursor:pointer;",
+                        onclick: move |_| { history.set(false); blame.set(false); files.set(!files()); },
+                        if files() { "✕ Close files" } else { "📎 Files" }
+                    }
+                    button {
+                        style: "padding:0.3rem 0.7rem;background:#3a3a5e;color:white;border:none;border-radius:4px;cursor:pointer;",
+                        onclick: move |_| sharing.set(true),
+                        "🔒 Share"
+                    }
+                }
+                if preview() || !can_edit {
+                    select {
+                        style: "padding:0.3rem;background:#3a3a5e;color:white;border:none;border-radius:4px;",
+                        title: "Code colours",
+                        value: "{code_theme}",
+                        onchange: move |e| {
+                            save_code_theme(&e.value());
+                            code_theme.set(e.value());
+                        },
+                        for theme in THEMES {
+                            option { value: "{theme}", selected: code_theme() == theme, "{theme}" }
+                        }
+                    }
                 }
                 button {
                     style: "padding:0.3rem 0.7rem;background:#3a3a5e;color:white;border:none;border-radius:4px;cursor:pointer;",
-                    onclick: move |_| preview.set(!preview()),
-                    if preview() { "✏ Edit" } else { "👁 Preview" }
+                    onclick: move |_| outline.set(!outline()),
+                    if outline() { "✕ Close outline" } else { "☰ Outline" }
                 }
                 button {
                     style: "padding:0.3rem 0.7rem;background:#3a3a5e;color:white;border:none;border-radius:4px;cursor:pointer;",
@@ -221,15 +602,68 @@ pub fn Editor(id: String) -> Element {
                 }
             }
 
-            if !preview() {
+            if let Some(message) = refused() {
+                div { style: "display:flex;align-items:center;gap:0.75rem;padding:0.5rem 1rem;background:#fdecea;color:#8a1c12;font-family:sans-serif;font-size:0.9rem;flex-shrink:0;",
+                    span { style: "flex:1;", "⚠ {message}" }
+                    button {
+                        style: "background:none;border:none;cursor:pointer;color:#8a1c12;",
+                        onclick: move |_| refused.set(None),
+                        "✕"
+                    }
+                }
+            }
+
+            if let Some(message) = throttled() {
+                div { style: "padding:0.5rem 1rem;background:#fff4d6;color:#7a5a00;font-family:sans-serif;font-size:0.9rem;flex-shrink:0;",
+                    "⏳ {message}"
+                }
+            }
+
+            if uploading() > 0 {
+                div { style: "padding:0.5rem 1rem;background:#fff4d6;color:#7a5a00;font-family:sans-serif;font-size:0.9rem;flex-shrink:0;",
+                    if uploading() == 1 { "⏳ Uploading file…" } else { "⏳ Uploading {uploading} files…" }
+                }
+            }
+
+            if can_edit && !preview() && !history() && !blame() && !files() {
                 Toolbar { on_action: handle_toolbar }
             }
 
+            if sharing() {
+                SharingDialog {
+                    id: id.clone(),
+                    on_close: move |_| sharing.set(false),
+                    on_changed: {
+                        let load_role = load_role.clone();
+                        move |_| load_role()
+                    },
+                }
+            }
+
             div { style: "flex:1;overflow:hidden;display:flex;",
-                if preview() {
+                if outline() && !history() && !blame() && !files() {
+                    OutlinePanel { headings: doc_headings(), caret: caret(), on_jump: jump_to_heading }
+                }
+                if history() {
+                    HistoryPanel { id: id.clone(), on_restored: move |_| history.set(false) }
+                } else if blame() {
+                    BlamePanel { id: id.clone() }
+                } else if files() {
+                    AttachmentsPanel {
+                        id: id.clone(),
+                        can_edit,
+                        on_uploaded: {
+                            let id = id.clone();
+                            move |a: reality::protocol::Attachment| insert_markdown(link_markdown(&a.name, &a.url(&id)))
+                        },
+                    }
+                } else if preview() || !can_edit {
+                    style { {MARKDOWN_STYLE} {theme_style()} }
                     div {
+                        class: "markdown",
                         style: "flex:1;padding:2rem;overflow:auto;max-width:800px;margin:0 auto;font-family:sans-serif;line-height:1.6;",
-                        dangerous_inner_html: render_markdown(&content.read())
+                        onclick: move |e| if can_edit { handle_preview_click(e) },
+                        dangerous_inner_html: render_markdown_with(&content.read(), RenderOptions { interactive_tasks: can_edit, highlight_code: true })
                     }
                 } else {
                     // holding already typed data for the preview
@@ -238,6 +672,29 @@ pub fn Editor(id: String) -> Element {
                         style: "flex:1;padding:1rem;font-family:'Fira Code',monospace;font-size:14px;line-height:1.6;border:none;resize:none;outline:none;background:#fafafa;width:100%;box-sizing:border-box;",
                         value: "{content}",
                         oninput: handle_input,
+                        onkeyup: move |_| caret.set(caret_in(&content.peek())),
+                        onmouseup: move |_| caret.set(caret_in(&content.peek())),
+                        onpaste: {
+                            let mut handle_files = handle_files.clone();
+                            move |e: Event<ClipboardData>| {
+                                let dropped = pasted_files(&e);
+                                if !dropped.is_empty() {
+                                    e.prevent_default();
+                                    handle_files(dropped);
+                                }
+                            }
+                        },
+                        ondragover: move |e: Event<DragData>| if dragging_files(&e) { e.prevent_default() },
+                        ondrop: {
+                            let mut handle_files = handle_files.clone();
+                            move |e: Event<DragData>| {
+                                let dropped = dropped_files(&e);
+                                if !dropped.is_empty() {
+                                    e.prevent_default();
+                                    handle_files(dropped);
+                                }
+                            }
+                        },
                     }
                 }
             }
@@ -245,6 +702,14 @@ pub fn Editor(id: String) -> Element {
     }
 }
 
+// the source position of the task box that was clicked, if it was one
+fn clicked_task(e: &Event<MouseData>) -> Option<usize> {
+    use wasm_bindgen::JsCast;
+    let event  = e.data().downcast::<web_sys::MouseEvent>()?.clone();
+    let target = event.target()?.dyn_into::<web_sys::Element>().ok()?;
+    target.get_attribute("data-task")?.parse().ok()
+}
+
 fn adjust_cursor(cursor: usize, splice_at: usize, del: usize, ins_len: usize) -> usize {
     if cursor <= splice_at {
         cursor
@@ -255,6 +720,16 @@ fn adjust_cursor(cursor: usize, splice_at: usize, del: usize, ins_len: usize) ->
     }
 }
 
+// snippet in place of the selection, cursor after it
+fn insert_at(content: &str, snippet: &str, sel_start: usize, sel_end: usize) -> (String, usize) {
+    let chars: Vec<char> = content.chars().collect();
+    let sel_start = sel_start.min(chars.len());
+    let sel_end   = sel_end.clamp(sel_start, chars.len());
+    let before: String = chars[..sel_start].iter().collect();
+    let after:  String = chars[sel_end..].iter().collect();
+    (format!("{before}{snippet}{after}"), sel_start + snippet.chars().count())
+}
+
 fn apply_toolbar_action_at_cursor(
     content: &str,
     action: ToolbarAction,
@@ -289,15 +764,30 @@ fn apply_toolbar_action_at_cursor(
             let cursor = sel_start + 1 + inner.chars().count() + 2 + url.chars().count() + 1;
             (format!("{}[{}]({}){}", before, inner, url, after), cursor)
         }
+        // a table needs a blank line above it or it's read as part of the paragraph
+        ToolbarAction::Table => {
+            let leading  = if before.is_empty() || before.ends_with("\n\n") { "" } else if before.ends_with('\n') { "\n" } else { "\n\n" };
+            let trailing = if !after.is_empty() && !after.starts_with('\n') { "\n" } else { "" };
+            let cursor   = sel_start + leading.len() + NEW_TABLE.chars().count() + trailing.len();
+            (format!("{}{}{}{}{}", before, leading, NEW_TABLE, trailing, after), cursor)
+        }
+        ToolbarAction::TableEdit(edit) => {
+            edit_table(content, sel_start, edit).unwrap_or_else(|| (content.to_string(), sel_start))
+        }
         _ => {
             // toolbar actions lol
+            let code_block;
             let snippet: &str = match action {
-                ToolbarAction::CodeBlock    => "```\ncode here\n```",
+                ToolbarAction::CodeBlock(lang) => {
+                    code_block = format!("```{lang}\ncode here\n```");
+                    &code_block
+                }
                 ToolbarAction::Heading(1)   => "# Heading 1",
                 ToolbarAction::Heading(2)   => "## Heading 2",
                 ToolbarAction::Heading(3)   => "### Heading 3",
                 ToolbarAction::BulletList   => "- list item",
                 ToolbarAction::NumberedList => "1. list item",
+                ToolbarAction::TaskList     => "- [ ] task",
                 ToolbarAction::Quote        => "> blockquote",
                 ToolbarAction::HRule        => "---",
                 _                           => return (content.to_string(), sel_start),
@@ -310,18 +800,6 @@ fn apply_toolbar_action_at_cursor(
     }
 }
 
-fn decode_payload(bytes: &[u8]) -> Option<(String, String, Vec<u8>)> {
-    let v: serde_json::Value = serde_json::from_slice(bytes).ok()?;
-    let text      = v.get("text")?.as_str()?.to_string();
-    let sender_id = v.get("sender_id")?.as_str()?.to_string();
-    let full_doc  = v.get("full_doc")?
-        .as_array()?
-        .iter()
-        .filter_map(|b| b.as_u64().map(|n| n as u8))
-        .collect();
-    Some((text, sender_id, full_doc))
-}
-
 fn download_md(content: &str) {
     use wasm_bindgen::JsCast;
     let window   = web_sys::window().unwrap();
@@ -337,104 +815,3 @@ fn download_md(content: &str) {
     a.click();
     web_sys::Url::revoke_object_url(&url).unwrap();
 }
-
-// visual effects, maybe one day i can go back and make it one function with a list or something, but did it the hard and long way for now
-
-fn render_markdown(md: &str) -> String {
-    let mut output         = String::new();
-    let mut in_code_block  = false;
-    let mut in_list        = false;
-    for line in md.lines() {
-        if line.starts_with("```") {
-            if in_code_block {
-                output.push_str("</code></pre>\n");
-                in_code_block = false;
-            } else {
-                if in_list { output.push_str("</ul>\n"); in_list = false; }
-                output.push_str("<pre><code>");
-                in_code_block = true;
-            }
-            continue;
-        }
-        if in_code_block { output.push_str(&html_escape(line)); output.push('\n'); continue; }
-        if in_list && !line.starts_with("- ") { output.push_str("</ul>\n"); in_list = false; }
-        let rendered = if let Some(h) = line.strip_prefix("### ") {
-            format!("<h3>{}</h3>\n", inline_md(h))
-        } else if let Some(h) = line.strip_prefix("## ") {
-            format!("<h2>{}</h2>\n", inline_md(h))
-        } else if let Some(h) = line.strip_prefix("# ") {
-            format!("<h1>{}</h1>\n", inline_md(h))
-        } else if let Some(item) = line.strip_prefix("- ") {
-            if !in_list { output.push_str("<ul>\n"); in_list = true; }
-            format!("<li>{}</li>\n", inline_md(item))
-        } else if let Some(item) = line.strip_prefix("> ") {
-            format!("<blockquote>{}</blockquote>\n", inline_md(item))
-        } else if line == "---" {
-            "<hr>\n".to_string()
-        } else if line.is_empty() {
-            "<br>\n".to_string()
-        } else {
-            format!("<p>{}</p>\n", inline_md(line))
-        };
-        output.push_str(&rendered);
-    }
-    if in_list { output.push_str("</ul>\n"); }
-    if in_code_block { output.push_str("</code></pre>\n"); }
-    output
-}
-
-fn inline_md(s: &str) -> String {
-    let s = html_escape(s);
-    let s = replace_inline(&s, '`', '`', "<code>", "</code>");
-    let s = replace_bold(&s);
-    let s = replace_inline(&s, '_', '_', "<em>", "</em>");
-    replace_links(&s)
-}
-
-fn html_escape(s: &str) -> String {
-    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
-}
-
-fn replace_bold(s: &str) -> String {
-    let mut result = String::new();
-    let mut chars  = s.chars().peekable();
-    let mut open   = false;
-    while let Some(c) = chars.next() {
-        if c == '*' && chars.peek() == Some(&'*') {
-            chars.next();
-            if open { result.push_str("</strong>"); } else { result.push_str("<strong>"); }
-            open = !open;
-        } else { result.push(c); }
-    }
-    result
-}
-
-fn replace_inline(s: &str, open_char: char, close_char: char, open_tag: &str, close_tag: &str) -> String {
-    let mut result = String::new();
-    let mut inside = false;
-    for c in s.chars() {
-        if c == open_char && !inside { result.push_str(open_tag); inside = true; }
-        else if c == close_char && inside { result.push_str(close_tag); inside = false; }
-        else { result.push(c); }
-    }
-    result
-}
-
-fn replace_links(s: &str) -> String {
-    let mut result = s.to_string();
-    while let Some(start) = result.find('[') {
-        if let Some(mid) = result[start..].find("](") {
-            let mid = start + mid;
-            if let Some(end) = result[mid..].find(')') {
-                let end  = mid + end;
-                let text = result[start+1..mid].to_string();
-                let url  = result[mid+2..end].to_string();
-                let link = format!("<a href=\"{}\" target=\"_blank\">{}</a>", url, text);
-                result   = format!("{}{}{}", &result[..start], link, &result[end+1..]);
-                continue;
-            }
-        }
-        break;
-    }
-    result
-}
\ No newline at end of file
@@ -0,0 +1,43 @@
+use dioxus::prelude::*;
+use reality::markdown::{heading_at, Heading};
+
+#[derive(Props, Clone, PartialEq)]
+pub struct OutlinePanelProps {
+    pub headings: Vec<Heading>,
+    // char index of the caret, the section it's in is the one highlighted
+    pub caret:    usize,
+    pub on_jump:  EventHandler<Heading>,
+}
+
+// the doc's headings down the side. they come from the text, so other people's edits show up
+// here as soon as they show up in the doc
+#[component]
+pub fn OutlinePanel(props: OutlinePanelProps) -> Element {
+    let current = heading_at(&props.headings, props.caret);
+    let top     = props.headings.iter().map(|h| h.level).min().unwrap_or(1);
+
+    rsx! {
+        div { style: "width:220px;flex-shrink:0;overflow:auto;padding:0.75rem 0;background:#f4f4f8;border-right:1px solid #e0e0ea;font-family:sans-serif;font-size:0.85rem;",
+            div { style: "padding:0 1rem 0.5rem;font-size:0.75rem;opacity:0.6;text-transform:uppercase;", "Outline" }
+            if props.headings.is_empty() {
+                div { style: "padding:0 1rem;opacity:0.6;", "No headings yet, start a line with # to add one." }
+            }
+            for (i, heading) in props.headings.iter().cloned().enumerate() {
+                div {
+                    key: "{heading.id}",
+                    style: format!(
+                        "padding:0.25rem 1rem 0.25rem {}rem;cursor:pointer;white-space:nowrap;overflow:hidden;text-overflow:ellipsis;{}",
+                        1.0 + 0.8 * (heading.level - top) as f32,
+                        if current == Some(i) { "background:#dcdcf0;font-weight:bold;" } else { "" },
+                    ),
+                    title: "{heading.text}",
+                    onclick: {
+                        let heading = heading.clone();
+                        move |_| props.on_jump.call(heading.clone())
+                    },
+                    "{heading.text}"
+                }
+            }
+        }
+    }
+}
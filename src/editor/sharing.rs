@@ -0,0 +1,281 @@
+use dioxus::prelude::*;
+use reality::protocol::{Grant, Role, Sharing};
+use super::lock::set_doc_password;
+use crate::account::TokenManager;
+
+// Err(status) so the editor can tell "no access" (403) apart from everything else
+pub async fn fetch_sharing(id: &str) -> Result<Sharing, u16> {
+    let resp = gloo_net::http::Request::get(&format!("/api/doc/{id}/sharing"))
+        .send().await.map_err(|_| 0u16)?;
+    if !resp.ok() { return Err(resp.status()); }
+    resp.json().await.map_err(|_| 0u16)
+}
+
+async fn save_sharing(id: &str, link_role: Option<Role>, grants: &[Grant]) -> Result<(), String> {
+    let body = serde_json::json!({ "link_role": link_role, "grants": grants });
+    let resp = gloo_net::http::Request::put(&format!("/api/doc/{id}/sharing"))
+        .json(&body).map_err(|e| e.to_string())?
+        .send().await.map_err(|e| e.to_string())?;
+    if !resp.ok() {
+        return Err(resp.text().await.unwrap_or_else(|_| "couldn't save".to_string()));
+    }
+    Ok(())
+}
+
+async fn claim(id: &str) -> Result<(), String> {
+    let resp = gloo_net::http::Request::post(&format!("/api/doc/{id}/claim"))
+        .send().await.map_err(|e| e.to_string())?;
+    if !resp.ok() {
+        return Err(resp.text().await.unwrap_or_else(|_| "sign in to claim this doc".to_string()));
+    }
+    Ok(())
+}
+
+// owner isn't in here on purpose, there's only one and it isn't handed out from this dialog
+const GRANTABLE: [(Role, &str); 3] = [
+    (Role::Viewer,    "viewer"),
+    (Role::Commenter, "commenter"),
+    (Role::Editor,    "editor"),
+];
+
+fn role_from_str(s: &str) -> Option<Role> {
+    GRANTABLE.iter().find(|(_, name)| *name == s).map(|(role, _)| *role)
+}
+
+fn role_name(role: Role) -> &'static str {
+    match role {
+        Role::Viewer    => "viewer",
+        Role::Commenter => "commenter",
+        Role::Editor    => "editor",
+        Role::Owner     => "owner",
+    }
+}
+
+#[component]
+pub fn SharingDialog(id: String, on_close: EventHandler<()>, on_changed: EventHandler<()>) -> Element {
+    let mut sharing   = use_signal(|| None::<Sharing>);
+    let mut link_role = use_signal(|| None::<Role>);
+    let mut grants    = use_signal(Vec::<Grant>::new);
+    let mut new_user  = use_signal(String::new);
+    let mut error     = use_signal(String::new);
+    let mut password  = use_signal(String::new);
+
+    use_effect({
+        let id = id.clone();
+        move || {
+            let id = id.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                if let Ok(s) = fetch_sharing(&id).await {
+                    link_role.set(s.link_role);
+                    grants.set(s.grants.clone());
+                    sharing.set(Some(s));
+                }
+            });
+        }
+    });
+
+    let Some(current) = sharing.read().clone() else {
+        return rsx! {};
+    };
+    let is_owner = current.my_role == Some(Role::Owner);
+
+    rsx! {
+        div { style: "position:fixed;inset:0;background:rgba(0,0,0,0.4);display:flex;align-items:center;justify-content:center;z-index:10;",
+            div { style: "background:white;border-radius:8px;padding:1.5rem;width:24rem;font-family:sans-serif;display:flex;flex-direction:column;gap:0.75rem;",
+                div { style: "display:flex;align-items:center;",
+                    span { style: "font-weight:bold;flex:1;", "Sharing" }
+                    button {
+                        style: "background:none;border:none;cursor:pointer;font-size:1rem;",
+                        onclick: move |_| on_close.call(()),
+                        "✕"
+                    }
+                }
+
+                if !is_owner {
+                    p { style: "margin:0;font-size:0.9rem;color:#555;",
+                        "You're a {current.my_role.map(role_name).unwrap_or(\"guest\")} here."
+                    }
+                    if current.owner.is_none() && current.my_role.is_some_and(Role::can_edit) {
+                        p { style: "margin:0;font-size:0.8rem;color:#777;",
+                            "Nobody owns this doc yet. Claiming it lets you share it with more people, everyone who has the link keeps their access."
+                        }
+                        button {
+                            style: "padding:0.4rem 0.8rem;background:#1a1a2e;color:white;border:none;border-radius:4px;cursor:pointer;",
+                            onclick: {
+                                let id = id.clone();
+                                move |_| {
+                                    let id = id.clone();
+                                    wasm_bindgen_futures::spawn_local(async move {
+                                        match claim(&id).await {
+                                            Ok(()) => {
+                                                if let Ok(s) = fetch_sharing(&id).await {
+                                                    link_role.set(s.link_role);
+                                                    grants.set(s.grants.clone());
+                                                    sharing.set(Some(s));
+                                                }
+                                                on_changed.call(());
+                                            }
+                                            Err(e) => error.set(e),
+                                        }
+                                    });
+                                }
+                            },
+                            "Claim this doc"
+                        }
+                    }
+                } else {
+                    label { style: "font-size:0.85rem;display:flex;align-items:center;gap:0.5rem;",
+                        "Anyone with the link:"
+                        select {
+                            value: "{link_role().map(role_name).unwrap_or(\"none\")}",
+                            onchange: move |e| link_role.set(role_from_str(&e.value())),
+                            // a claimed doc keeps what the link got people before
+                            if current.link_floor.is_none() {
+                                option { value: "none", "no access" }
+                            }
+                            for (_, name) in GRANTABLE.iter().filter(|(role, _)| Some(*role) >= current.link_floor) {
+                                option { value: "{name}", "{name}" }
+                            }
+                        }
+                    }
+
+                    div { style: "display:flex;flex-direction:column;gap:0.35rem;",
+                        for (i, grant) in grants.read().iter().cloned().enumerate() {
+                            div { key: "{grant.username}", style: "display:flex;align-items:center;gap:0.5rem;font-size:0.85rem;",
+                                span { style: "flex:1;", "{grant.username}" }
+                                select {
+                                    value: "{role_name(grant.role)}",
+                                    onchange: move |e| {
+                                        if let Some(role) = role_from_str(&e.value()) {
+                                            grants.write()[i].role = role;
+                                        }
+                                    },
+                                    for (_, name) in GRANTABLE {
+                                        option { value: "{name}", "{name}" }
+                                    }
+                                }
+                                button {
+                                    style: "background:none;border:none;cursor:pointer;color:#c00;",
+                                    onclick: move |_| { grants.write().remove(i); },
+                                    "✕"
+                                }
+                            }
+                        }
+                    }
+
+                    div { style: "display:flex;gap:0.5rem;",
+                        input {
+                            style: "flex:1;padding:0.3rem 0.5rem;border:1px solid #ccc;border-radius:4px;",
+                            placeholder: "Username",
+                            value: "{new_user}",
+                            oninput: move |e| new_user.set(e.value()),
+                        }
+                        button {
+                            style: "padding:0.3rem 0.7rem;background:#3a3a5e;color:white;border:none;border-radius:4px;cursor:pointer;",
+                            onclick: move |_| {
+                                let username = new_user.read().trim().to_string();
+                                if username.is_empty() || grants.read().iter().any(|g| g.username.eq_ignore_ascii_case(&username)) {
+                                    return;
+                                }
+                                grants.write().push(Grant { username, role: Role::Viewer });
+                                new_user.set(String::new());
+                            },
+                            "Add"
+                        }
+                    }
+
+                    // a password on top of the link, for when making everyone sign up is too much
+                    if current.link_floor.is_some() {
+                        p { style: "margin:0;font-size:0.8rem;color:#777;border-top:1px solid #eee;padding-top:0.75rem;",
+                            "This doc was claimed, so people who had the link before keep their access and it can't get a password."
+                        }
+                    } else {
+                        div { style: "display:flex;flex-direction:column;gap:0.35rem;border-top:1px solid #eee;padding-top:0.75rem;",
+                            span { style: "font-size:0.85rem;",
+                                if current.has_password { "🔒 People opening the link need a password" } else { "No password on this doc" }
+                            }
+                            div { style: "display:flex;gap:0.5rem;",
+                                input {
+                                    r#type: "password",
+                                    style: "flex:1;padding:0.3rem 0.5rem;border:1px solid #ccc;border-radius:4px;",
+                                    placeholder: if current.has_password { "New password" } else { "Password" },
+                                    value: "{password}",
+                                    oninput: move |e| password.set(e.value()),
+                                }
+                                button {
+                                    style: "padding:0.3rem 0.7rem;background:#3a3a5e;color:white;border:none;border-radius:4px;cursor:pointer;",
+                                    onclick: {
+                                        let id = id.clone();
+                                        move |_| {
+                                            let id = id.clone();
+                                            let pw = password.read().clone();
+                                            if pw.is_empty() { return; }
+                                            wasm_bindgen_futures::spawn_local(async move {
+                                                match set_doc_password(&id, Some(&pw)).await {
+                                                    Ok(()) => {
+                                                        password.set(String::new());
+                                                        error.set(String::new());
+                                                        if let Some(s) = sharing.write().as_mut() { s.has_password = true; }
+                                                    }
+                                                    Err(e) => error.set(e),
+                                                }
+                                            });
+                                        }
+                                    },
+                                    if current.has_password { "Change" } else { "Set" }
+                                }
+                                if current.has_password {
+                                    button {
+                                        style: "padding:0.3rem 0.7rem;background:none;color:#c00;border:1px solid #c00;border-radius:4px;cursor:pointer;",
+                                        onclick: {
+                                            let id = id.clone();
+                                            move |_| {
+                                                let id = id.clone();
+                                                wasm_bindgen_futures::spawn_local(async move {
+                                                    match set_doc_password(&id, None).await {
+                                                        Ok(()) => {
+                                                            if let Some(s) = sharing.write().as_mut() { s.has_password = false; }
+                                                        }
+                                                        Err(e) => error.set(e),
+                                                    }
+                                                });
+                                            }
+                                        },
+                                        "Remove"
+                                    }
+                                }
+                            }
+                        }
+                    }
+
+                    details { style: "border-top:1px solid #eee;padding-top:0.75rem;font-size:0.85rem;",
+                        summary { style: "cursor:pointer;", "API tokens for this doc" }
+                        TokenManager { doc_id: Some(id.clone()) }
+                    }
+
+                    button {
+                        style: "padding:0.4rem 0.8rem;background:#1a1a2e;color:white;border:none;border-radius:4px;cursor:pointer;",
+                        onclick: {
+                            let id = id.clone();
+                            move |_| {
+                                let id = id.clone();
+                                let (role, list) = (link_role(), grants.read().clone());
+                                wasm_bindgen_futures::spawn_local(async move {
+                                    match save_sharing(&id, role, &list).await {
+                                        Ok(()) => { error.set(String::new()); on_close.call(()); }
+                                        Err(e) => error.set(e),
+                                    }
+                                });
+                            }
+                        },
+                        "Save"
+                    }
+                }
+
+                if !error.read().is_empty() {
+                    p { style: "margin:0;font-size:0.8rem;color:#c00;", "{error}" }
+                }
+            }
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use dioxus::prelude::*;
+use reality::table::TableEdit;
 
 #[derive(Props, Clone, PartialEq)]
 pub struct ToolbarProps {
@@ -12,13 +13,18 @@ pub enum ToolbarAction {
     Bold,
     Italic,
     Code,
-    CodeBlock,
+    // the language after the backticks, the editor asks for it when the button is pressed
+    CodeBlock(String),
     Heading(u8),
     Link,
     BulletList,
     NumberedList,
+    TaskList,
     Quote,
     HRule,
+    Table,
+    // rows, columns and re-aligning the table the cursor is in
+    TableEdit(TableEdit),
 }
 
 #[component]
@@ -28,27 +34,37 @@ pub fn Toolbar(props: ToolbarProps) -> Element {
         div {
             style: "display:flex;gap:0.25rem;padding:0.4rem 1rem;background:#2a2a3e;border-bottom:1px solid #3a3a5e;flex-wrap:wrap;",
 
-            ToolbarBtn { label: "B", title: "Bold (ctrl+b)", action: ToolbarAction::Bold, on_action: props.on_action.clone(), bold: true }
-            ToolbarBtn { label: "I", title: "Italic (ctrl+i)", action: ToolbarAction::Italic, on_action: props.on_action.clone(), italic: true }
-            ToolbarBtn { label: "`", title: "Inline code", action: ToolbarAction::Code, on_action: props.on_action.clone(), bold: false, italic: false }
-            ToolbarBtn { label: "```", title: "Code block", action: ToolbarAction::CodeBlock, on_action: props.on_action.clone(), bold: false, italic: false }
+            ToolbarBtn { label: "B", title: "Bold (ctrl+b)", action: ToolbarAction::Bold, on_action: props.on_action, bold: true }
+            ToolbarBtn { label: "I", title: "Italic (ctrl+i)", action: ToolbarAction::Italic, on_action: props.on_action, italic: true }
+            ToolbarBtn { label: "`", title: "Inline code", action: ToolbarAction::Code, on_action: props.on_action, bold: false, italic: false }
+            ToolbarBtn { label: "```", title: "Code block", action: ToolbarAction::CodeBlock(String::new()), on_action: props.on_action, bold: false, italic: false }
 
             div { style: "width:1px;background:#3a3a5e;margin:0 0.25rem;" }
 
-            ToolbarBtn { label: "H1", title: "Heading 1", action: ToolbarAction::Heading(1), on_action: props.on_action.clone(), bold: false, italic: false }
-            ToolbarBtn { label: "H2", title: "Heading 2", action: ToolbarAction::Heading(2), on_action: props.on_action.clone(), bold: false, italic: false }
-            ToolbarBtn { label: "H3", title: "Heading 3", action: ToolbarAction::Heading(3), on_action: props.on_action.clone(), bold: false, italic: false }
+            ToolbarBtn { label: "H1", title: "Heading 1", action: ToolbarAction::Heading(1), on_action: props.on_action, bold: false, italic: false }
+            ToolbarBtn { label: "H2", title: "Heading 2", action: ToolbarAction::Heading(2), on_action: props.on_action, bold: false, italic: false }
+            ToolbarBtn { label: "H3", title: "Heading 3", action: ToolbarAction::Heading(3), on_action: props.on_action, bold: false, italic: false }
 
             div { style: "width:1px;background:#3a3a5e;margin:0 0.25rem;" }
 
-            ToolbarBtn { label: "•", title: "Bullet list", action: ToolbarAction::BulletList, on_action: props.on_action.clone(), bold: false, italic: false }
-            ToolbarBtn { label: "1.", title: "Numbered list", action: ToolbarAction::NumberedList, on_action: props.on_action.clone(), bold: false, italic: false }
-            ToolbarBtn { label: "❝", title: "Blockquote", action: ToolbarAction::Quote, on_action: props.on_action.clone(), bold: false, italic: false }
-            ToolbarBtn { label: "—", title: "Horizontal rule", action: ToolbarAction::HRule, on_action: props.on_action.clone(), bold: false, italic: false }
+            ToolbarBtn { label: "•", title: "Bullet list", action: ToolbarAction::BulletList, on_action: props.on_action, bold: false, italic: false }
+            ToolbarBtn { label: "1.", title: "Numbered list", action: ToolbarAction::NumberedList, on_action: props.on_action, bold: false, italic: false }
+            ToolbarBtn { label: "☐", title: "Task list", action: ToolbarAction::TaskList, on_action: props.on_action, bold: false, italic: false }
+            ToolbarBtn { label: "❝", title: "Blockquote", action: ToolbarAction::Quote, on_action: props.on_action, bold: false, italic: false }
+            ToolbarBtn { label: "—", title: "Horizontal rule", action: ToolbarAction::HRule, on_action: props.on_action, bold: false, italic: false }
 
             div { style: "width:1px;background:#3a3a5e;margin:0 0.25rem;" }
 
-            ToolbarBtn { label: "🔗", title: "Link", action: ToolbarAction::Link, on_action: props.on_action.clone(), bold: false, italic: false }
+            ToolbarBtn { label: "🔗", title: "Link", action: ToolbarAction::Link, on_action: props.on_action, bold: false, italic: false }
+
+            div { style: "width:1px;background:#3a3a5e;margin:0 0.25rem;" }
+
+            ToolbarBtn { label: "⊞", title: "Insert table", action: ToolbarAction::Table, on_action: props.on_action }
+            ToolbarBtn { label: "+row", title: "Add a row below the cursor", action: ToolbarAction::TableEdit(TableEdit::AddRow), on_action: props.on_action }
+            ToolbarBtn { label: "−row", title: "Remove the row the cursor is in", action: ToolbarAction::TableEdit(TableEdit::RemoveRow), on_action: props.on_action }
+            ToolbarBtn { label: "+col", title: "Add a column right of the cursor", action: ToolbarAction::TableEdit(TableEdit::AddColumn), on_action: props.on_action }
+            ToolbarBtn { label: "−col", title: "Remove the column the cursor is in", action: ToolbarAction::TableEdit(TableEdit::RemoveColumn), on_action: props.on_action }
+            ToolbarBtn { label: "⇹", title: "Line up the table's columns", action: ToolbarAction::TableEdit(TableEdit::Align), on_action: props.on_action }
         }
     }
 }
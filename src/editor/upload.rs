@@ -0,0 +1,51 @@
+use dioxus::prelude::*;
+use wasm_bindgen::JsCast;
+use reality::protocol::Asset;
+
+// files out of a paste or a drop, a paste of plain text has none and is left to the textarea
+fn files(data: Option<web_sys::DataTransfer>) -> Vec<web_sys::File> {
+    let Some(files) = data.and_then(|d| d.files()) else { return Vec::new() };
+    (0..files.length()).filter_map(|i| files.get(i)).collect()
+}
+
+pub fn pasted_files(e: &Event<ClipboardData>) -> Vec<web_sys::File> {
+    let event = e.data().downcast::<web_sys::Event>().and_then(|e| e.dyn_ref::<web_sys::ClipboardEvent>().cloned());
+    files(event.and_then(|e| e.clipboard_data()))
+}
+
+pub fn dropped_files(e: &Event<DragData>) -> Vec<web_sys::File> {
+    files(e.data().downcast::<web_sys::DragEvent>().and_then(|e| e.data_transfer()))
+}
+
+// the kinds /assets takes and the preview shows inline, anything else becomes an attachment
+pub fn is_image(file: &web_sys::File) -> bool {
+    matches!(file.type_().as_str(), "image/png" | "image/jpeg" | "image/gif" | "image/webp")
+}
+
+// the browser only lets a drop through if dragover was cancelled, but text drags
+// should still work the normal way
+pub fn dragging_files(e: &Event<DragData>) -> bool {
+    let Some(data) = e.data().downcast::<web_sys::DragEvent>().and_then(|e| e.data_transfer()) else { return false };
+    data.types().iter().any(|t| t.as_string().as_deref() == Some("Files"))
+}
+
+// the server checks size and type too, and its messages are the ones people see
+pub async fn upload_image(id: &str, file: &web_sys::File) -> Result<Asset, String> {
+    let resp = gloo_net::http::Request::post(&format!("/api/doc/{id}/assets"))
+        .header("Content-Type", &file.type_())
+        .body(file.clone()).map_err(|e| e.to_string())?
+        .send().await.map_err(|e| e.to_string())?;
+    if !resp.ok() {
+        return Err(resp.text().await.unwrap_or_else(|_| "couldn't upload that image".to_string()));
+    }
+    resp.json().await.map_err(|e| e.to_string())
+}
+
+// alt text from the file name, pasted screenshots all come in as "image.png"
+pub fn alt_text(file: &web_sys::File) -> String {
+    let name = file.name();
+    match name.rsplit_once('.') {
+        Some((stem, _)) if !stem.is_empty() => stem.to_string(),
+        _                                    => name,
+    }
+}
@@ -0,0 +1,60 @@
+// syntax highlighting for fenced code, syntect with its pure rust regex engine so the same
+// code runs in the wasm preview and on the server for published pages. the html only has
+// classes in it, colours come from whichever theme's css the page includes
+use std::sync::OnceLock;
+use pulldown_cmark_escape::escape_html;
+use syntect::highlighting::ThemeSet;
+use syntect::html::{css_for_theme_with_class_style, ClassStyle, ClassedHTMLGenerator};
+use syntect::parsing::SyntaxSet;
+use syntect::util::LinesWithEndings;
+
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+// the themes syntect ships with, the first is what published pages use
+pub const THEMES: [&str; 7] = [
+    "InspiredGitHub",
+    "Solarized (light)",
+    "base16-ocean.light",
+    "Solarized (dark)",
+    "base16-ocean.dark",
+    "base16-eighties.dark",
+    "base16-mocha.dark",
+];
+
+pub const DEFAULT_THEME: &str = THEMES[0];
+
+// loading these is the slow part, so it happens once and only when a code block needs it
+fn syntaxes() -> &'static SyntaxSet {
+    static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+fn themes() -> &'static ThemeSet {
+    static THEME_SET: OnceLock<ThemeSet> = OnceLock::new();
+    THEME_SET.get_or_init(ThemeSet::load_defaults)
+}
+
+// the whole <pre> for a fenced block, None when we don't know the language so the caller
+// falls back to a plain block. lang is whatever followed the backticks, "rust" or "rs" both work
+pub fn highlight_code(code: &str, lang: &str) -> Option<String> {
+    let lang   = lang.split_whitespace().next()?;
+    let syntax = syntaxes().find_syntax_by_token(lang)?;
+    let mut generator = ClassedHTMLGenerator::new_with_class_style(syntax, syntaxes(), CLASS_STYLE);
+    for line in LinesWithEndings::from(code) {
+        generator.parse_html_for_line_which_includes_newline(line).ok()?;
+    }
+    let mut html = String::from("<pre class=\"hl-code\"><code class=\"language-");
+    let _ = escape_html(&mut html, lang);
+    html.push_str("\">");
+    html.push_str(&generator.finalize());
+    html.push_str("</code></pre>\n");
+    Some(html)
+}
+
+// colours for the classes above. the hl- prefix keeps them from clashing with anything else,
+// only the block itself needs to beat the plain `.markdown pre` background
+pub fn theme_css(name: &str) -> String {
+    let theme = themes().themes.get(name).or_else(|| themes().themes.get(DEFAULT_THEME));
+    let css   = theme.and_then(|t| css_for_theme_with_class_style(t, CLASS_STYLE).ok()).unwrap_or_default();
+    css.replace(".hl-code {", ".markdown pre.hl-code {")
+}
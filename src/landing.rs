@@ -1,18 +1,21 @@
 #![allow(non_snake_case)]
 use dioxus::prelude::*;
+use crate::account::AccountBox;
 use crate::Route;
 
 // landing webpage, not much to say here.
 
 #[component]
 pub fn Landing() -> Element {
-    let mut join_id = use_signal(|| String::new());
+    let mut join_id = use_signal(String::new);
     let nav = use_navigator();
 
     rsx! {
         div { style: "display:flex;flex-direction:column;align-items:center;justify-content:center;height:100vh;gap:1rem;",
             h1 { "Reality" }
 
+            AccountBox {}
+
             button {
                 onclick: move |_| {
                     let id = uuid();
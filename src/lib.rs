@@ -0,0 +1,9 @@
+// everything both the web editor and the server need, no feature flags in here
+// so it builds the same for wasm and native and the tests run with plain cargo test
+pub mod diff;
+pub mod doc;
+pub mod highlight;
+pub mod markdown;
+pub mod math;
+pub mod protocol;
+pub mod table;
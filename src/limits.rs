@@ -0,0 +1,163 @@
+// caps on what one client can make the server hold, each one overridable with an env var
+const FRAME_ENV:   &str = "REALITY_MAX_FRAME_BYTES";
+const DOC_ENV:     &str = "REALITY_MAX_DOC_CHARS";
+const HISTORY_ENV: &str = "REALITY_MAX_HISTORY_BYTES";
+const ASSET_ENV:   &str = "REALITY_MAX_ASSET_BYTES";
+const ATTACHMENT_ENV: &str = "REALITY_MAX_ATTACHMENT_BYTES";
+// and on how fast, see rate.rs
+const SOCKET_MSGS_ENV:  &str = "REALITY_SOCKET_MESSAGES_PER_SEC";
+const SOCKET_BYTES_ENV: &str = "REALITY_SOCKET_BYTES_PER_SEC";
+const IP_MSGS_ENV:      &str = "REALITY_IP_MESSAGES_PER_SEC";
+const IP_BYTES_ENV:     &str = "REALITY_IP_BYTES_PER_SEC";
+const IP_SOCKETS_ENV:   &str = "REALITY_MAX_SOCKETS_PER_IP";
+const DOC_SOCKETS_ENV:  &str = "REALITY_MAX_SOCKETS_PER_DOC";
+// set to 1 behind a reverse proxy, otherwise every client looks like the proxy
+const FORWARDED_ENV:    &str = "REALITY_TRUST_FORWARDED_FOR";
+
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    // one websocket message, typing sends a few hundred bytes so this only bites on huge pastes
+    pub max_frame_bytes:   usize,
+    // length of the text itself
+    pub max_doc_chars:     usize,
+    // size of the saved .am, which keeps every change ever made
+    pub max_history_bytes: usize,
+    // one uploaded image, screenshots of a big monitor are a few MB
+    pub max_asset_bytes:   usize,
+    // one attached file, logs and pdfs run bigger than screenshots
+    pub max_attachment_bytes: usize,
+    // typing is a message every keystroke or two, so these leave plenty of room for a fast typist
+    pub socket_messages_per_sec: usize,
+    pub socket_bytes_per_sec:    usize,
+    // shared by every tab from one address
+    pub ip_messages_per_sec:     usize,
+    pub ip_bytes_per_sec:        usize,
+    pub max_sockets_per_ip:      usize,
+    pub max_sockets_per_doc:     usize,
+    pub trust_forwarded_for:     bool,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_frame_bytes:   512 * 1024,
+            max_doc_chars:     1_000_000,
+            max_history_bytes: 50 * 1024 * 1024,
+            max_asset_bytes:   10 * 1024 * 1024,
+            max_attachment_bytes: 25 * 1024 * 1024,
+            socket_messages_per_sec: 30,
+            socket_bytes_per_sec:    128 * 1024,
+            ip_messages_per_sec:     100,
+            ip_bytes_per_sec:        512 * 1024,
+            max_sockets_per_ip:      20,
+            max_sockets_per_doc:     100,
+            trust_forwarded_for:     false,
+        }
+    }
+}
+
+impl Limits {
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let limits = Self {
+            max_frame_bytes:   env_or(FRAME_ENV, defaults.max_frame_bytes),
+            max_doc_chars:     env_or(DOC_ENV, defaults.max_doc_chars),
+            max_history_bytes: env_or(HISTORY_ENV, defaults.max_history_bytes),
+            max_asset_bytes:   env_or(ASSET_ENV, defaults.max_asset_bytes),
+            max_attachment_bytes: env_or(ATTACHMENT_ENV, defaults.max_attachment_bytes),
+            socket_messages_per_sec: env_or(SOCKET_MSGS_ENV, defaults.socket_messages_per_sec),
+            socket_bytes_per_sec:    env_or(SOCKET_BYTES_ENV, defaults.socket_bytes_per_sec),
+            ip_messages_per_sec:     env_or(IP_MSGS_ENV, defaults.ip_messages_per_sec),
+            ip_bytes_per_sec:        env_or(IP_BYTES_ENV, defaults.ip_bytes_per_sec),
+            max_sockets_per_ip:      env_or(IP_SOCKETS_ENV, defaults.max_sockets_per_ip),
+            max_sockets_per_doc:     env_or(DOC_SOCKETS_ENV, defaults.max_sockets_per_doc),
+            trust_forwarded_for:     env_or(FORWARDED_ENV, 0) != 0,
+        };
+        println!(
+            "Limits: {} per message, {} characters per doc, {} of history per doc, {} per image, {} per attachment",
+            human_size(limits.max_frame_bytes),
+            limits.max_doc_chars,
+            human_size(limits.max_history_bytes),
+            human_size(limits.max_asset_bytes),
+            human_size(limits.max_attachment_bytes),
+        );
+        println!(
+            "Rates: {} messages and {}/s per socket, {} messages and {}/s per address, {} sockets per address, {} per doc",
+            limits.socket_messages_per_sec,
+            human_size(limits.socket_bytes_per_sec),
+            limits.ip_messages_per_sec,
+            human_size(limits.ip_bytes_per_sec),
+            limits.max_sockets_per_ip,
+            limits.max_sockets_per_doc,
+        );
+        limits
+    }
+
+    // the socket and the rest api say the same thing, so the editor and scripts match
+    pub fn too_long_message(&self) -> String {
+        format!("Documents can't be longer than {} characters, that change was not saved.", self.max_doc_chars)
+    }
+
+    pub fn too_big_message(&self, len: usize) -> String {
+        format!(
+            "That change is {}, more than the {} one edit can be, so it was not saved.",
+            human_size(len),
+            human_size(self.max_frame_bytes),
+        )
+    }
+
+    pub fn asset_too_big_message(&self) -> String {
+        format!("Images can be at most {}, that one was not uploaded.", human_size(self.max_asset_bytes))
+    }
+
+    pub fn attachment_too_big_message(&self) -> String {
+        format!("Attachments can be at most {}, that file was not uploaded.", human_size(self.max_attachment_bytes))
+    }
+
+    pub fn throttled_message(&self, wait: std::time::Duration) -> String {
+        format!(
+            "You're sending edits faster than the server takes them, they'll be saved in {:.1}s.",
+            wait.as_secs_f64(),
+        )
+    }
+
+    pub fn too_many_sockets_message(&self) -> String {
+        format!(
+            "Too many open documents from your network (at most {}), close a tab and reload.",
+            self.max_sockets_per_ip,
+        )
+    }
+
+    pub fn doc_full_message(&self) -> String {
+        format!(
+            "This document already has {} people connected, try again in a bit.",
+            self.max_sockets_per_doc,
+        )
+    }
+
+    pub fn history_full_message(&self) -> String {
+        format!(
+            "This document's history has reached {}, the most this server keeps, so it can't take more edits.",
+            human_size(self.max_history_bytes),
+        )
+    }
+}
+
+fn env_or(name: &str, default: usize) -> usize {
+    match std::env::var(name) {
+        Ok(v) => v.trim().parse().unwrap_or_else(|_| {
+            eprintln!("{name}={v} isn't a number, using {default}");
+            default
+        }),
+        Err(_) => default,
+    }
+}
+
+// for messages people will read
+fn human_size(bytes: usize) -> String {
+    match bytes {
+        b if b >= 1024 * 1024 => format!("{:.1} MB", b as f64 / (1024.0 * 1024.0)),
+        b if b >= 1024        => format!("{} KB", b / 1024),
+        b                     => format!("{b} bytes"),
+    }
+}
@@ -0,0 +1,262 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+use axum::{
+    extract::{ConnectInfo, Path, State},
+    http::{header, HeaderMap, StatusCode},
+    response::IntoResponse,
+    routing::{post, put},
+    Json, Router,
+};
+use dashmap::DashMap;
+use serde::Deserialize;
+use reality::protocol::Role;
+use crate::api::authorize;
+use crate::auth::{cookie_value, hash_password, is_https, password_matches, Caller};
+use crate::rate::{client_ip, too_many_guesses};
+use crate::state::{now_secs, AppState};
+
+const PASS_COOKIE: &str = "reality_pass";
+// only has to outlive the trip from the password screen to the socket upgrade,
+// an open socket keeps working after it runs out
+const PASS_TTL_SECS: i64 = 60 * 60;
+const MIN_PASSWORD_CHARS: usize = 4;
+
+#[derive(Clone)]
+struct Pass {
+    docs:    Vec<String>,
+    expires: i64,
+}
+
+// what typing a doc password gets you, lives in memory only like sessions
+#[derive(Clone, Default)]
+pub struct DocPasses {
+    passes: Arc<DashMap<String, Pass>>,
+}
+
+impl DocPasses {
+    // swaps whatever pass the browser already had for a fresh one that also covers doc_id
+    fn grant(&self, previous: Option<&str>, doc_id: &str) -> String {
+        let mut docs = previous
+            .and_then(|token| self.passes.remove(token))
+            .filter(|(_, pass)| pass.expires >= now_secs())
+            .map(|(_, pass)| pass.docs)
+            .unwrap_or_default();
+        if !docs.iter().any(|d| d == doc_id) {
+            docs.push(doc_id.to_string());
+        }
+        let token = format!("{}{}", uuid::Uuid::new_v4().simple(), uuid::Uuid::new_v4().simple());
+        self.passes.insert(token.clone(), Pass { docs, expires: now_secs() + PASS_TTL_SECS });
+        token
+    }
+
+    pub fn allows(&self, headers: &HeaderMap, doc_id: &str) -> bool {
+        let Some(token) = cookie_value(headers, PASS_COOKIE) else { return false; };
+        let Some(pass) = self.passes.get(&token).map(|p| p.clone()) else { return false; };
+        if pass.expires < now_secs() {
+            self.passes.remove(&token);
+            return false;
+        }
+        pass.docs.iter().any(|d| d == doc_id)
+    }
+
+    // changing or removing the password kicks out everyone who typed the old one
+    fn forget(&self, doc_id: &str) {
+        for mut pass in self.passes.iter_mut() {
+            pass.docs.retain(|d| d != doc_id);
+        }
+        self.passes.retain(|_, pass| !pass.docs.is_empty());
+    }
+}
+
+// Secure only over https, same as the session cookie
+fn pass_cookie(token: &str, secure: bool) -> String {
+    let secure = if secure { " Secure;" } else { "" };
+    format!("{PASS_COOKIE}={token}; Path=/; HttpOnly;{secure} SameSite=Strict; Max-Age={PASS_TTL_SECS}")
+}
+
+// None or empty takes the password off
+fn wanted_password(password: Option<String>) -> Result<Option<String>, &'static str> {
+    match password.filter(|p| !p.is_empty()) {
+        Some(p) if p.chars().count() < MIN_PASSWORD_CHARS => Err("password must be at least 4 characters"),
+        password => Ok(password),
+    }
+}
+
+pub fn lock_router(state: AppState) -> Router {
+    Router::new()
+        .route("/api/doc/:id/unlock", post(unlock))
+        .route("/api/doc/:id/password", put(set_password))
+        .with_state(state)
+}
+
+#[derive(Deserialize)]
+struct UnlockBody {
+    password: String,
+}
+
+#[derive(Deserialize)]
+struct PasswordBody {
+    // None or empty takes the password off
+    password: Option<String>,
+}
+
+async fn unlock(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    caller: Caller,
+    Json(body): Json<UnlockBody>,
+) -> impl IntoResponse {
+    let Some(room) = state.get_room(&id) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let (role, hash) = {
+        let meta = room.meta.lock().await;
+        (caller.role(&id, &meta), meta.password_hash.clone())
+    };
+    if role.is_none() {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+    let Some(hash) = hash else {
+        return StatusCode::NO_CONTENT.into_response();
+    };
+    let ip     = client_ip(addr, &headers, &state.limits);
+    let target = format!("doc:{id}");
+    if let Err(wait) = state.guesses.take(ip, &target) {
+        return too_many_guesses(wait);
+    }
+    let ok = tokio::task::spawn_blocking(move || password_matches(&hash, &body.password)).await;
+    match ok {
+        Ok(true) => {
+            state.guesses.right(ip, &target);
+            let token = state.passes.grant(cookie_value(&headers, PASS_COOKIE).as_deref(), &id);
+            (StatusCode::NO_CONTENT, [(header::SET_COOKIE, pass_cookie(&token, is_https(&headers)))]).into_response()
+        }
+        Ok(false) => (StatusCode::UNAUTHORIZED, "wrong password").into_response(),
+        Err(_)    => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+async fn set_password(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    caller: Caller,
+    Json(body): Json<PasswordBody>,
+) -> impl IntoResponse {
+    let room = match authorize(&state, &id, &caller, Role::Owner).await {
+        Ok(room) => room,
+        Err(resp) => return resp,
+    };
+    let password = match wanted_password(body.password) {
+        Ok(password) => password,
+        Err(message) => return (StatusCode::BAD_REQUEST, message).into_response(),
+    };
+    let hash = match password {
+        Some(p) => match tokio::task::spawn_blocking(move || hash_password(&p)).await {
+            Ok(Some(hash)) => Some(hash),
+            _              => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        },
+        None => None,
+    };
+    let mut meta = room.meta.lock().await;
+    if hash.is_some() && !meta.can_lock() {
+        return (StatusCode::BAD_REQUEST, "this doc was claimed, a password would shut out people who already had the link").into_response();
+    }
+    meta.password_hash = hash;
+    meta.persist(&id);
+    state.passes.forget(&id);
+    StatusCode::NO_CONTENT.into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::meta::DocMeta;
+    use crate::state::Room;
+
+    #[test]
+    fn passwords_have_a_minimum_length() {
+        assert_eq!(wanted_password(None), Ok(None));
+        assert_eq!(wanted_password(Some(String::new())), Ok(None));
+        assert!(wanted_password(Some("abc".into())).is_err());
+        assert_eq!(wanted_password(Some("abcd".into())), Ok(Some("abcd".into())));
+        // counted in characters, not bytes
+        assert!(wanted_password(Some("éàü".into())).is_err());
+    }
+
+    fn pass_headers(token: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::COOKIE, format!("{PASS_COOKIE}={token}").parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn passes_cover_their_docs_until_they_expire() {
+        let passes = DocPasses::default();
+        let first  = passes.grant(None, "a");
+        assert!(passes.allows(&pass_headers(&first), "a"));
+        assert!(!passes.allows(&pass_headers(&first), "b"));
+        assert!(!passes.allows(&HeaderMap::new(), "a"));
+
+        // unlocking a second doc swaps the old pass for one covering both
+        let second = passes.grant(Some(&first), "b");
+        assert!(!passes.allows(&pass_headers(&first), "a"));
+        assert!(passes.allows(&pass_headers(&second), "a") && passes.allows(&pass_headers(&second), "b"));
+
+        // changing a's password only takes a away
+        passes.forget("a");
+        assert!(!passes.allows(&pass_headers(&second), "a") && passes.allows(&pass_headers(&second), "b"));
+
+        passes.passes.get_mut(&second).unwrap().expires = now_secs() - 1;
+        assert!(!passes.allows(&pass_headers(&second), "b"));
+        assert!(passes.passes.is_empty());
+        // an expired pass isn't carried over either
+        let third = passes.grant(Some(&second), "c");
+        assert_eq!(passes.passes.get(&third).unwrap().docs, ["c"]);
+        assert!(pass_cookie(&third, false).contains(&format!("Max-Age={PASS_TTL_SECS}")));
+    }
+
+    #[tokio::test]
+    async fn unlocking_lets_link_holders_in() {
+        let state = AppState::in_memory();
+        let meta  = DocMeta { password_hash: hash_password("hunter22"), ..DocMeta::default() };
+        state.rooms.insert("notes".into(), Room::new("secret").with_meta(meta));
+
+        let stranger = Caller::for_test(&state, &[]).await;
+        let refused  = authorize(&state, "notes", &stranger, Role::Viewer).await.err().unwrap();
+        assert_eq!(refused.status(), StatusCode::UNAUTHORIZED);
+
+        let unlock_with = |password: &str| unlock(
+            Path("notes".to_string()),
+            State(state.clone()),
+            ConnectInfo("10.0.0.1:5000".parse().unwrap()),
+            HeaderMap::new(),
+            stranger.clone(),
+            Json(UnlockBody { password: password.to_string() }),
+        );
+        assert_eq!(unlock_with("hunter2").await.into_response().status(), StatusCode::UNAUTHORIZED);
+        let resp = unlock_with("hunter22").await.into_response();
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        let cookie = resp.headers()[header::SET_COOKIE].to_str().unwrap().to_string();
+        assert!(!cookie.contains("Secure"), "{cookie}");
+
+        let pass     = cookie.split(';').next().unwrap();
+        let unlocked = Caller::for_test(&state, &[("cookie", pass)]).await;
+        assert!(authorize(&state, "notes", &unlocked, Role::Editor).await.is_ok());
+        assert!(authorize(&state, "other", &unlocked, Role::Viewer).await.is_err());
+
+        // guessing gets cut off before the password is even checked
+        let mut last = StatusCode::UNAUTHORIZED;
+        for _ in 0..20 {
+            last = unlock_with("wrong").await.into_response().status();
+            if last != StatusCode::UNAUTHORIZED {
+                break;
+            }
+        }
+        assert_eq!(last, StatusCode::TOO_MANY_REQUESTS);
+        let resp = unlock_with("hunter22").await.into_response();
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(resp.headers().contains_key(header::RETRY_AFTER));
+    }
+}
@@ -2,10 +2,11 @@
 
 use dioxus::prelude::*;
 // this hosts the front end, route allows for room codes
+mod account;
 mod editor;
 mod landing;
 
-use editor::Editor;
+use editor::{Editor, View};
 use landing::Landing;
 
 #[derive(Clone, Routable, Debug, PartialEq)]
@@ -14,6 +15,8 @@ enum Route {
     Landing {},
     #[route("/doc/:id")]
     Editor { id: String },
+    #[route("/view/:token")]
+    View { token: String },
 }
 
 fn main() {
@@ -0,0 +1,632 @@
+// markdown -> html, plain rust so both the wasm editor and the server can use it.
+// pulldown-cmark does the parsing (commonmark plus the github tables, strikethrough, task
+// lists, footnotes and definition lists, and $ math), we only step in on the events that could
+// put someone else's html in the page, to give headings and footnotes ids to link to, and to
+// swap a [TOC] paragraph for a list of the headings
+use std::collections::{HashMap, HashSet};
+use pulldown_cmark::{CodeBlockKind, CowStr, Event, LinkType, Options, Parser, Tag, TagEnd};
+use pulldown_cmark_escape::{escape_href, escape_html};
+use crate::highlight::highlight_code;
+use crate::math::{latex_to_mathml, math_error};
+
+// looks for what render_markdown puts out, scoped to a .markdown container so the editor
+// preview and the published page (and nothing else) pick it up
+pub const MARKDOWN_STYLE: &str = ".markdown pre{background:#f4f4f8;padding:1rem;overflow:auto;}\
+.markdown code{font-family:'Fira Code',monospace;}\
+.markdown blockquote{border-left:4px solid #ccc;margin:0;padding-left:1rem;color:#555;}\
+.markdown table{border-collapse:collapse;margin:1rem 0;}\
+.markdown th,.markdown td{border:1px solid #ccc;padding:0.3rem 0.75rem;}\
+.markdown th{background:#f4f4f8;}\
+.markdown img{max-width:100%;}\
+.markdown li:has(> input[type=checkbox]){list-style:none;margin-left:-1.2rem;}\
+.markdown input[type=checkbox]{margin-right:0.4rem;}\
+.markdown input.task{cursor:pointer;}\
+.markdown math[display=block]{margin:1rem 0;}\
+.markdown .math-error{color:#b00020;}\
+.markdown .math-error code{text-decoration:underline wavy #b00020;}\
+.markdown dt{font-weight:bold;}\
+.markdown dd{margin:0 0 0.5rem 1.5rem;}\
+.markdown .footnote-ref a,.markdown .footnote-backref{text-decoration:none;}\
+.markdown .footnotes{border-top:1px solid #ddd;margin-top:2rem;font-size:0.9em;color:#444;}\
+.markdown :target{background:#fff8c5;}\
+.markdown .toc{background:#f8f8fb;border:1px solid #e4e4ec;border-radius:4px;padding:0.5rem 1rem;margin:1rem 0;}\
+.markdown .toc ul{margin:0;padding-left:1.2rem;}";
+
+fn options() -> Options {
+    Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS | Options::ENABLE_MATH
+        | Options::ENABLE_FOOTNOTES | Options::ENABLE_DEFINITION_LIST
+}
+
+// what the page showing the markdown lets people do with it
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct RenderOptions {
+    // task checkboxes can be clicked, each one carries the char index of its [ ] in the source
+    pub interactive_tasks: bool,
+    // fenced blocks with a language we know get coloured, see highlight.rs
+    pub highlight_code:    bool,
+}
+
+pub fn render_markdown(md: &str) -> String {
+    render_markdown_with(md, RenderOptions::default())
+}
+
+// a heading as the outline and #anchor links see it, at is the char index of its first # (or its text)
+#[derive(Clone, PartialEq, Debug)]
+pub struct Heading {
+    pub level: u8,
+    pub text:  String,
+    pub id:    String,
+    pub at:    usize,
+}
+
+// github style ids: lowercase, punctuation dropped, spaces to dashes, repeats get -1, -2...
+#[derive(Default)]
+struct Slugs(HashSet<String>);
+
+impl Slugs {
+    fn next(&mut self, text: &str) -> String {
+        let base: String = text
+            .trim()
+            .to_lowercase()
+            .chars()
+            .filter_map(|c| match c {
+                c if c.is_alphanumeric() || c == '-' || c == '_' => Some(c),
+                c if c.is_whitespace()                           => Some('-'),
+                _                                                => None,
+            })
+            .collect();
+        let base = if base.is_empty() { "section".to_string() } else { base };
+        let mut slug = base.clone();
+        let mut n    = 0;
+        while self.0.contains(&slug) {
+            n += 1;
+            slug = format!("{base}-{n}");
+        }
+        self.0.insert(slug.clone());
+        slug
+    }
+}
+
+// every heading in the doc in order, with the id render_markdown gives it
+pub fn headings(md: &str) -> Vec<Heading> {
+    let mut slugs   = Slugs::default();
+    let mut chars   = (0, 0);
+    let mut current: Option<(u8, String, usize)> = None;
+    let mut found   = Vec::new();
+    for (event, range) in Parser::new_ext(md, options()).into_offset_iter() {
+        match event {
+            Event::Start(Tag::Heading { level, .. }) => {
+                chars = (range.start, chars.1 + md[chars.0..range.start].chars().count());
+                current = Some((level as u8, String::new(), chars.1));
+            }
+            Event::Text(text) | Event::Code(text) | Event::InlineMath(text) => {
+                if let Some((_, heading, _)) = &mut current {
+                    heading.push_str(&text);
+                }
+            }
+            Event::End(TagEnd::Heading(_)) => {
+                if let Some((level, text, at)) = current.take() {
+                    let id = slugs.next(&text);
+                    found.push(Heading { level, text: text.trim().to_string(), id, at });
+                }
+            }
+            _ => {}
+        }
+    }
+    found
+}
+
+// which of the headings the char index `at` falls under, None when it's above the first one
+pub fn heading_at(headings: &[Heading], at: usize) -> Option<usize> {
+    headings.iter().rposition(|h| h.at <= at)
+}
+
+// what a paragraph of just [TOC] turns into, the headings as nested lists of links. a level
+// that skips one (# then ###) only goes one list deeper
+fn toc(headings: &[Heading]) -> String {
+    let Some(top) = headings.iter().map(|h| h.level).min() else { return String::new() };
+    let mut html  = String::from("<nav class=\"toc\">");
+    let mut depth = 0;
+    for heading in headings {
+        let level = (heading.level - top + 1) as usize;
+        let level = level.min(depth + 1);
+        if level > depth {
+            html.push_str("\n<ul>\n");
+            depth = level;
+        } else {
+            html.push_str("</li>\n");
+            while depth > level {
+                html.push_str("</ul>\n</li>\n");
+                depth -= 1;
+            }
+        }
+        html.push_str(&format!("<li><a href=\"#{}\">{}</a>", html_escape(&heading.id), html_escape(&heading.text)));
+    }
+    html.push_str("</li>\n");
+    while depth > 1 {
+        html.push_str("</ul>\n</li>\n");
+        depth -= 1;
+    }
+    html.push_str("</ul>\n</nav>\n");
+    html
+}
+
+// footnotes are numbered in the order they're first referenced, whatever their labels are
+#[derive(Default)]
+struct Footnotes {
+    // label -> number and how many times it's been referenced so far
+    refs: HashMap<String, (usize, usize)>,
+}
+
+impl Footnotes {
+    fn reference(&mut self, label: &str) -> String {
+        let next = self.refs.len() + 1;
+        let (n, count) = self.refs.entry(label.to_lowercase()).or_insert((next, 0));
+        *count += 1;
+        let id = if *count == 1 { format!("footnote-ref-{n}") } else { format!("footnote-ref-{n}-{count}") };
+        format!("<sup class=\"footnote-ref\"><a href=\"#footnote-{n}\" id=\"{id}\">{n}</a></sup>")
+    }
+
+    // the list at the bottom, each note with a link back to every place it was referenced.
+    // notes nobody referenced are left out, like github does
+    fn section<'a>(&self, definitions: Vec<(CowStr<'a>, Vec<Event<'a>>)>) -> Vec<Event<'a>> {
+        let mut numbered: Vec<_> = definitions
+            .into_iter()
+            .filter_map(|(label, events)| self.refs.get(&label.to_lowercase()).map(|&(n, count)| (n, count, events)))
+            .collect();
+        numbered.sort_by_key(|(n, _, _)| *n);
+        numbered.dedup_by_key(|(n, _, _)| *n);
+        if numbered.is_empty() {
+            return Vec::new();
+        }
+        let mut out = vec![Event::Html("<section class=\"footnotes\">\n<ol>\n".into())];
+        for (n, count, mut events) in numbered {
+            let backrefs: String = (1..=count)
+                .map(|k| {
+                    let (id, mark) = if k == 1 { (format!("footnote-ref-{n}"), String::new()) } else { (format!("footnote-ref-{n}-{k}"), format!("<sup>{k}</sup>")) };
+                    format!(" <a href=\"#{id}\" class=\"footnote-backref\" aria-label=\"Back to reference {n}\">↩{mark}</a>")
+                })
+                .collect();
+            // inside the last paragraph so the arrow sits at the end of the text
+            let at = match events.last() {
+                Some(Event::End(TagEnd::Paragraph)) => events.len() - 1,
+                _                                   => events.len(),
+            };
+            events.insert(at, Event::Html(backrefs.into()));
+            out.push(Event::Html(format!("<li id=\"footnote-{n}\">\n").into()));
+            out.extend(events);
+            out.push(Event::Html("</li>\n".into()));
+        }
+        out.push(Event::Html("</ol>\n</section>\n".into()));
+        out
+    }
+}
+
+pub fn render_markdown_with(md: &str, opts: RenderOptions) -> String {
+    // one entry per open link, whether we wrote its <a> and so owe a </a>
+    let mut links  = Vec::new();
+    // byte offsets from the parser turned into char indices as we go, it only ever moves forward
+    let mut chars  = (0, 0);
+    // a fenced block with a language, held back until it ends so it's highlighted in one go
+    let mut fence: Option<(CowStr, String)> = None;
+    let all_headings    = headings(md);
+    let mut heading_ids = all_headings.iter().map(|h| h.id.clone());
+    // set while skipping the text of a [TOC] paragraph, the list went in its place
+    let mut in_toc      = false;
+    let mut footnotes   = Footnotes::default();
+    // footnote definitions are read into their own list and go at the bottom
+    let mut definition: Option<(CowStr, Vec<Event>)> = None;
+    let mut definitions = Vec::new();
+    let mut main   = Vec::new();
+    for (event, range) in Parser::new_ext(md, options()).into_offset_iter() {
+        match event {
+            Event::Start(Tag::FootnoteDefinition(label)) => {
+                definition = Some((label, Vec::new()));
+                continue;
+            }
+            Event::End(TagEnd::FootnoteDefinition) => {
+                definitions.extend(definition.take());
+                continue;
+            }
+            _ => {}
+        }
+        let events = match &mut definition {
+            Some((_, events)) => events,
+            None              => &mut main,
+        };
+        match event {
+            Event::Start(Tag::Paragraph) if md[range.clone()].trim().eq_ignore_ascii_case("[toc]") => {
+                events.push(Event::Html(toc(&all_headings).into()));
+                in_toc = true;
+            }
+            Event::End(TagEnd::Paragraph) if in_toc => in_toc = false,
+            _ if in_toc => {}
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) if opts.highlight_code && !info.trim().is_empty() => {
+                fence = Some((info, String::new()));
+            }
+            Event::Text(text) if fence.is_some() => {
+                if let Some((_, code)) = &mut fence {
+                    code.push_str(&text);
+                }
+            }
+            Event::End(TagEnd::CodeBlock) if fence.is_some() => {
+                let Some((info, code)) = fence.take() else { continue };
+                match highlight_code(&code, &info) {
+                    Some(html) => events.push(Event::Html(html.into())),
+                    None       => events.extend([
+                        Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))),
+                        Event::Text(code.into()),
+                        Event::End(TagEnd::CodeBlock),
+                    ]),
+                }
+            }
+            Event::TaskListMarker(checked) if opts.interactive_tasks => {
+                chars = (range.start, chars.1 + md[chars.0..range.start].chars().count());
+                events.push(Event::Html(task_checkbox(chars.1 + 1, checked).into()));
+            }
+            Event::InlineMath(tex)  => events.push(Event::Html(math(&tex, false).into())),
+            Event::DisplayMath(tex) => events.push(Event::Html(math(&tex, true).into())),
+            Event::Start(Tag::Heading { level, id, classes, attrs }) => {
+                let id = id.or_else(|| heading_ids.next().map(CowStr::from));
+                events.push(Event::Start(Tag::Heading { level, id, classes, attrs }));
+            }
+            Event::FootnoteReference(label) => events.push(Event::Html(footnotes.reference(&label).into())),
+            event => events.extend(safe_event(event, &mut links)),
+        }
+    }
+    // a definition the doc never closed still counts
+    definitions.extend(definition.take());
+    main.extend(footnotes.section(definitions));
+    let mut output = String::new();
+    pulldown_cmark::html::push_html(&mut output, main.into_iter());
+    output
+}
+
+// a formula that doesn't parse shows its source and why, the rest of the page is unaffected
+fn math(tex: &str, display: bool) -> String {
+    latex_to_mathml(tex, display).unwrap_or_else(|e| math_error(tex, display, &e))
+}
+
+fn task_checkbox(at: usize, checked: bool) -> String {
+    format!("<input type=\"checkbox\" class=\"task\" data-task=\"{at}\"{}>\n", if checked { " checked" } else { "" })
+}
+
+// the text with the task box at char index `at` ticked or unticked, None if there isn't one
+// there any more (someone edited the line since the preview was drawn)
+pub fn toggle_task(md: &str, at: usize) -> Option<String> {
+    let mut chars: Vec<char> = md.chars().collect();
+    let mark = match (chars.get(at.checked_sub(1)?)?, chars.get(at)?, chars.get(at + 1)?) {
+        ('[', ' ', ']')       => 'x',
+        ('[', 'x' | 'X', ']') => ' ',
+        _                     => return None,
+    };
+    chars[at] = mark;
+    Some(chars.into_iter().collect())
+}
+
+// [text](url) for an uploaded file, brackets in the text would end it early
+pub fn link_markdown(text: &str, url: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars().filter(|c| !c.is_control()) {
+        if matches!(c, '\\' | '[' | ']') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    format!("[{escaped}]({url})")
+}
+
+pub fn image_markdown(alt: &str, url: &str) -> String {
+    format!("!{}", link_markdown(alt, url))
+}
+
+// raw html is shown as the text it is, links and images only keep urls with a safe scheme.
+// anything else goes to push_html, which escapes text and attributes itself
+fn safe_event<'a>(event: Event<'a>, links: &mut Vec<bool>) -> Option<Event<'a>> {
+    match event {
+        Event::Html(html) | Event::InlineHtml(html) => Some(Event::Text(html)),
+        Event::Start(Tag::HtmlBlock) => Some(Event::Start(Tag::Paragraph)),
+        Event::End(TagEnd::HtmlBlock) => Some(Event::End(TagEnd::Paragraph)),
+        Event::Start(Tag::Link { link_type, dest_url, title, .. }) => {
+            let href = match link_type {
+                LinkType::Email => format!("mailto:{dest_url}"),
+                _               => dest_url.to_string(),
+            };
+            let safe = is_safe_url(&href);
+            links.push(safe);
+            safe.then(|| Event::Html(link_open(&href, &title).into()))
+        }
+        Event::End(TagEnd::Link) => links.pop().unwrap_or(false).then(|| Event::Html("</a>".into())),
+        Event::Start(Tag::Image { link_type, dest_url, title, id }) if !is_safe_url(&dest_url) => {
+            Some(Event::Start(Tag::Image { link_type, dest_url: CowStr::Borrowed(""), title, id }))
+        }
+        event => Some(event),
+    }
+}
+
+// what push_html would write, plus opening other pages in a new tab without handing it our window
+fn link_open(href: &str, title: &str) -> String {
+    let mut tag = String::from("<a href=\"");
+    let _ = escape_href(&mut tag, href);
+    if !title.is_empty() {
+        tag.push_str("\" title=\"");
+        let _ = escape_html(&mut tag, title);
+    }
+    // links to a spot on the same page stay in it
+    if href.starts_with('#') {
+        tag.push_str("\">");
+    } else {
+        tag.push_str("\" target=\"_blank\" rel=\"noopener noreferrer\">");
+    }
+    tag
+}
+
+// for text going into a page we build by hand, quotes included so it's safe in attributes too
+pub fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+// schemes a link may use, anything else (javascript:, data:, vbscript:...) is rendered as plain text
+const SAFE_SCHEMES: [&str; 3] = ["http", "https", "mailto"];
+
+// browsers skip tabs, newlines and leading spaces when they work out a url's scheme, so
+// "java\tscript:" still runs script. the check has to see the url the way they do
+pub fn is_safe_url(url: &str) -> bool {
+    let cleaned: String = url.chars().filter(|c| !c.is_ascii_whitespace() && !c.is_control()).collect();
+    let scheme_end = cleaned.find(':');
+    let path_start = cleaned.find(['/', '?', '#']);
+    match (scheme_end, path_start) {
+        // no colon, or the colon comes after the path starts, so it's relative to this page
+        (None, _) => true,
+        (Some(colon), Some(path)) if path < colon => true,
+        (Some(colon), _) => SAFE_SCHEMES.contains(&cleaned[..colon].to_ascii_lowercase().as_str()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_headings_lists_and_code() {
+        let html = render_markdown("# Title\n- one\n- two\n```\n<b>\n```");
+        assert_eq!(
+            html,
+            "<h1 id=\"title\">Title</h1>\n<ul>\n<li>one</li>\n<li>two</li>\n</ul>\n<pre><code>&lt;b&gt;\n</code></pre>\n"
+        );
+    }
+
+    #[test]
+    fn renders_inline_markup() {
+        assert_eq!(
+            render_markdown("**bold** and `code` and [x](https://a.b)"),
+            "<p><strong>bold</strong> and <code>code</code> and <a href=\"https://a.b\" target=\"_blank\" rel=\"noopener noreferrer\">x</a></p>\n"
+        );
+    }
+
+    // user text is all escaped, so every '<' left in the output opens a tag we wrote.
+    // those have to be tags we mean to emit, with no handlers and no script urls
+    const OUR_TAGS: [&str; 53] = [
+        "p", "h1", "h2", "h3", "h4", "h5", "h6", "ul", "ol", "li", "blockquote", "hr", "br", "pre", "code",
+        "strong", "em", "del", "a", "img", "input", "table", "thead", "tbody", "tr", "th", "td", "span",
+        "sup", "section", "dl", "dt", "dd", "nav",
+        "math", "mrow", "mi", "mn", "mo", "mtext", "mspace", "msup", "msub", "msubsup", "mover", "munder",
+        "munderover", "mfrac", "msqrt", "mroot", "mtable", "mtr", "mtd",
+    ];
+
+    fn assert_inert(html: &str) {
+        for tag in html.split('<').skip(1).map(|t| t.split('>').next().unwrap()) {
+            let tag  = tag.to_ascii_lowercase();
+            let name = tag.trim_start_matches('/').split([' ', '/']).next().unwrap();
+            assert!(OUR_TAGS.contains(&name), "<{tag}> in {html}");
+            // quotes in user text are escaped, so the real ones split names from values
+            for (i, part) in tag.split('"').enumerate() {
+                if i % 2 == 0 {
+                    assert!(!part.contains(" on"), "handler in <{tag}> in {html}");
+                } else {
+                    let value = part.trim_start();
+                    for bad in ["javascript:", "vbscript:", "data:"] {
+                        assert!(!value.starts_with(bad), "{bad} inside <{tag}> in {html}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn xss_payloads_are_inert() {
+        let payloads = [
+            "<script>alert(1)</script>",
+            "<img src=x onerror=alert(1)>",
+            "<svg/onload=alert(1)>",
+            "<iframe src=\"javascript:alert(1)\"></iframe>",
+            "[x](javascript:alert(1))",
+            "[x](JaVaScRiPt:alert(1))",
+            "[x](java\tscript:alert(1))",
+            "[x]( javascript:alert(1))",
+            "[x](\u{1}javascript:alert(1))",
+            "[x](vbscript:msgbox(1))",
+            "[x](data:text/html;base64,PHNjcmlwdD5hbGVydCgxKTwvc2NyaXB0Pg==)",
+            "[x](https://a.b\" onmouseover=\"alert(1))",
+            "[x](https://a.b' onmouseover='alert(1))",
+            "[<img src=x onerror=alert(1)>](https://a.b)",
+            "# <script>alert(1)</script>",
+            "- <b onclick=alert(1)>hi</b>",
+            "> <style>*{display:none}</style>",
+            "```\n</code></pre><script>alert(1)</script>\n```",
+            "**<script>**alert(1)**</script>**",
+            "`<script>`",
+            "[x](&#106;avascript:alert(1))",
+            "[x](java&#x09;script:alert(1))",
+            "![x](javascript:alert(1))",
+            "<javascript:alert(1)>",
+            "[x]: javascript:alert(1)\n\n[x]",
+            "[x](https://a.b \"t\\\" onmouseover=alert(1)\")",
+            "- [ ] <img src=x onerror=alert(1)>",
+            "| <script> |\n| --- |\n| x |",
+            "$\\text{<script>alert(1)</script>}$",
+            "$x<img src=x onerror=alert(1)>$",
+            "$$\\mathrm{\" onclick=\"alert(1)}$$",
+            "$\\unknown<script>$",
+        ];
+        for payload in payloads {
+            assert_inert(&render_markdown(payload));
+        }
+    }
+
+    #[test]
+    fn urls_are_checked_by_scheme() {
+        for ok in ["https://a.b", "http://a.b/c?d#e", "mailto:me@a.b", "/doc/abc", "#top", "notes.md", "?q=a:b", "//a.b/c"] {
+            assert!(is_safe_url(ok), "{ok} should be allowed");
+        }
+        for bad in ["javascript:alert(1)", "JAVASCRIPT:x", "java\nscript:x", "data:text/html,x", "vbscript:x", "file:///etc/passwd"] {
+            assert!(!is_safe_url(bad), "{bad} should be refused");
+        }
+    }
+
+    #[test]
+    fn fixes_what_the_old_renderer_got_wrong() {
+        assert_eq!(render_markdown("a snake_case_name"), "<p>a snake_case_name</p>\n");
+        assert_eq!(render_markdown("1. one\n2. two"), "<ol>\n<li>one</li>\n<li>two</li>\n</ol>\n");
+        assert_eq!(render_markdown("one\ntwo"), "<p>one\ntwo</p>\n");
+        assert_eq!(
+            render_markdown("- a\n  - b"),
+            "<ul>\n<li>a\n<ul>\n<li>b</li>\n</ul>\n</li>\n</ul>\n"
+        );
+        assert_eq!(render_markdown("> one\n> two"), "<blockquote>\n<p>one\ntwo</p>\n</blockquote>\n");
+    }
+
+    #[test]
+    fn renders_aligned_tables() {
+        let html = render_markdown("| a | b |\n| :-: | --: |\n| 1 | 2 |");
+        assert!(html.contains("<th style=\"text-align: center\">a</th>"), "{html}");
+        assert!(html.contains("<td style=\"text-align: right\">2</td>"), "{html}");
+    }
+
+    #[test]
+    fn task_boxes_point_at_their_source() {
+        let md = "- [ ] é\n- [x] done";
+        assert!(render_markdown(md).contains("<input disabled=\"\" type=\"checkbox\"/>"));
+        let html = render_markdown_with(md, RenderOptions { interactive_tasks: true, ..Default::default() });
+        assert!(html.contains("<input type=\"checkbox\" class=\"task\" data-task=\"3\">"), "{html}");
+        assert!(html.contains("<input type=\"checkbox\" class=\"task\" data-task=\"11\" checked>"), "{html}");
+        assert_eq!(toggle_task(md, 3).unwrap(), "- [x] é\n- [x] done");
+        assert_eq!(toggle_task(md, 11).unwrap(), "- [ ] é\n- [ ] done");
+        // stale positions do nothing
+        assert_eq!(toggle_task(md, 4), None);
+        assert_eq!(toggle_task(md, 100), None);
+    }
+
+    #[test]
+    fn highlights_known_languages_only() {
+        let opts = RenderOptions { highlight_code: true, ..Default::default() };
+        let html = render_markdown_with("```rust\nlet s = \"<b>\";\n```", opts);
+        assert!(html.starts_with("<pre class=\"hl-code\"><code class=\"language-rust\">"), "{html}");
+        assert!(html.contains("<span class=\"hl-storage hl-type hl-rust\">let</span>"), "{html}");
+        assert!(html.contains("&lt;b&gt;"), "{html}");
+        assert_eq!(
+            render_markdown_with("```nosuchlang\n<b>\n```", opts),
+            "<pre><code class=\"language-nosuchlang\">&lt;b&gt;\n</code></pre>\n"
+        );
+        assert_inert(&render_markdown_with("```js\n</code></pre><script>alert(1)</script>\n```", opts));
+        assert_inert(&render_markdown_with("```<img src=x onerror=alert(1)>\nx\n```", opts));
+    }
+
+    #[test]
+    fn raw_html_is_shown_as_text() {
+        assert_eq!(render_markdown("<div>hi</div>"), "<p>&lt;div&gt;hi&lt;/div&gt;</p>\n");
+        assert_eq!(render_markdown("a <b>b</b>"), "<p>a &lt;b&gt;b&lt;/b&gt;</p>\n");
+    }
+
+    #[test]
+    fn unsafe_links_keep_their_text() {
+        assert_eq!(render_markdown("[click](javascript:alert`1`)"), "<p>click</p>\n");
+    }
+
+    #[test]
+    fn renders_math_and_shows_errors_in_place() {
+        assert_eq!(
+            render_markdown("area $\\pi r^2$ here"),
+            "<p>area <math><mrow><mi>π</mi><msup><mi>r</mi><mn>2</mn></msup></mrow></math> here</p>\n"
+        );
+        assert!(render_markdown("$$\n\\frac{a}{b}\n$$").contains("<math display=\"block\"><mrow><mfrac>"));
+        assert_eq!(
+            render_markdown("$x^$ and **more**"),
+            "<p><span class=\"math-error\"><code>$x^$</code> ⚠ missing argument</span> and <strong>more</strong></p>\n"
+        );
+        // prices aren't formulas
+        assert_eq!(render_markdown("costs $5 or $10"), "<p>costs $5 or $10</p>\n");
+    }
+
+    #[test]
+    fn upload_references_round_trip() {
+        let md = image_markdown("shot [1]\n", "/assets/abc");
+        assert_eq!(md, "![shot \\[1\\]](/assets/abc)");
+        assert_eq!(render_markdown(&md), "<p><img src=\"/assets/abc\" alt=\"shot [1]\" /></p>\n");
+        let md = link_markdown("a]b.log", "/api/doc/d/attachments/f/a_b.log");
+        assert_eq!(render_markdown(&md), "<p><a href=\"/api/doc/d/attachments/f/a_b.log\" target=\"_blank\" rel=\"noopener noreferrer\">a]b.log</a></p>\n");
+    }
+
+    #[test]
+    fn headings_get_unique_ids() {
+        assert_eq!(
+            render_markdown("# Intro\n## Intro\n# Café & *crème*!\n[up](#intro)"),
+            "<h1 id=\"intro\">Intro</h1>\n<h2 id=\"intro-1\">Intro</h2>\n<h1 id=\"café--crème\">Café &amp; <em>crème</em>!</h1>\n<p><a href=\"#intro\">up</a></p>\n"
+        );
+        let found = headings("# Intro\ntext\n## Intro\n> # Quoted `code`\n\nSetext\n===\n");
+        let ids: Vec<_> = found.iter().map(|h| (h.level, h.text.as_str(), h.id.as_str(), h.at)).collect();
+        assert_eq!(ids, [(1, "Intro", "intro", 0), (2, "Intro", "intro-1", 13), (1, "Quoted code", "quoted-code", 24), (1, "Setext", "setext", 41)]);
+        assert_inert(&render_markdown("# \" onmouseover=\"alert(1)\n# <script>"));
+    }
+
+    #[test]
+    fn toc_marker_lists_headings() {
+        let html = render_markdown("[TOC]\n\n# One\n### Deep\n## Two & *more*\n# Three\n\n`[TOC]`\n\n    [TOC]\n");
+        assert_eq!(
+            html.split("<h1").next().unwrap(),
+            "<nav class=\"toc\">\n<ul>\n\
+             <li><a href=\"#one\">One</a>\n<ul>\n\
+             <li><a href=\"#deep\">Deep</a></li>\n\
+             <li><a href=\"#two--more\">Two &amp; more</a></li>\n</ul>\n</li>\n\
+             <li><a href=\"#three\">Three</a></li>\n</ul>\n</nav>\n"
+        );
+        assert!(html.contains("<p><code>[TOC]</code></p>") && html.contains("<pre><code>[TOC]\n</code></pre>"), "{html}");
+        assert_eq!(render_markdown("[toc]\n\nno headings"), "<p>no headings</p>\n");
+        assert_inert(&render_markdown("[TOC]\n# <img src=x onerror=alert(1)>"));
+
+        let found = headings("intro\n# A\ntext\n## B\n");
+        assert_eq!(heading_at(&found, 0), None);
+        assert_eq!(heading_at(&found, 6), Some(0));
+        assert_eq!(heading_at(&found, 14), Some(0));
+        assert_eq!(heading_at(&found, 99), Some(1));
+    }
+
+    #[test]
+    fn footnotes_link_both_ways() {
+        let html = render_markdown("Text[^b] and[^a] again[^b].\n\n[^a]: First.\n[^b]: Second\n\n[^unused]: x\n");
+        assert!(html.starts_with(
+            "<p>Text<sup class=\"footnote-ref\"><a href=\"#footnote-1\" id=\"footnote-ref-1\">1</a></sup> \
+             and<sup class=\"footnote-ref\"><a href=\"#footnote-2\" id=\"footnote-ref-2\">2</a></sup> \
+             again<sup class=\"footnote-ref\"><a href=\"#footnote-1\" id=\"footnote-ref-1-2\">1</a></sup>.</p>\n<section class=\"footnotes\">"
+        ), "{html}");
+        assert!(html.contains(
+            "<li id=\"footnote-1\">\n<p>Second <a href=\"#footnote-ref-1\" class=\"footnote-backref\" aria-label=\"Back to reference 1\">↩</a> \
+             <a href=\"#footnote-ref-1-2\" class=\"footnote-backref\" aria-label=\"Back to reference 1\">↩<sup>2</sup></a></p>\n</li>\n<li id=\"footnote-2\">"
+        ), "{html}");
+        assert!(!html.contains("unused") && !html.contains(">x<"), "{html}");
+        assert_eq!(render_markdown("see [^nope]"), "<p>see [^nope]</p>\n");
+        assert_inert(&render_markdown("a[^<x>]\n\n[^<x>]: <img src=x onerror=alert(1)>"));
+    }
+
+    #[test]
+    fn renders_definition_lists() {
+        assert_eq!(
+            render_markdown("Term\n: Definition one\n: two\n"),
+            "<dl>\n<dt>Term</dt>\n<dd>Definition one</dd>\n<dd>two</dd>\n</dl>\n"
+        );
+    }
+}
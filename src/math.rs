@@ -0,0 +1,602 @@
+// latex math -> mathml, which browsers draw natively so there's no js or font loading.
+// covers what people actually write in notes: scripts, fractions, roots, greek, the usual
+// operators and arrows, \left \right, accents, text, and matrix style environments.
+// anything else is an error the preview shows inline next to the source
+
+// deep enough for any real formula, shallow enough that a doc full of braces can't blow the stack
+const MAX_DEPTH: usize = 64;
+
+pub fn latex_to_mathml(tex: &str, display: bool) -> Result<String, String> {
+    let mut parser = Tex { chars: tex.chars().collect(), pos: 0, depth: 0 };
+    let (body, stop) = parser.expr()?;
+    match stop {
+        Stop::End    => {}
+        Stop::Brace  => return Err("unexpected }".into()),
+        Stop::Right  => return Err("\\right without a \\left".into()),
+        Stop::EndEnv => return Err("\\end without a \\begin".into()),
+        // & and \\ only mean something inside an environment
+        Stop::Cell | Stop::Row => return Err("& and \\\\ only work inside \\begin{...}".into()),
+    }
+    let display = if display { " display=\"block\"" } else { "" };
+    Ok(format!("<math{display}><mrow>{body}</mrow></math>"))
+}
+
+// the source and what went wrong, where the formula would have been
+pub fn math_error(tex: &str, display: bool, error: &str) -> String {
+    let delim = if display { "$$" } else { "$" };
+    format!("<span class=\"math-error\"><code>{delim}{}{delim}</code> ⚠ {}</span>", esc(tex), esc(error))
+}
+
+fn esc(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+// what ended an expression
+#[derive(PartialEq, Debug)]
+enum Stop {
+    End,
+    Brace,
+    Cell,
+    Row,
+    Right,
+    EndEnv,
+}
+
+struct Node {
+    xml:    String,
+    // sums, integrals, lim and friends put their scripts above and below in display math
+    limits: bool,
+}
+
+impl Node {
+    fn new(xml: String) -> Self {
+        Self { xml, limits: false }
+    }
+}
+
+struct Tex {
+    chars: Vec<char>,
+    pos:   usize,
+    depth: usize,
+}
+
+fn greek(name: &str) -> Option<char> {
+    Some(match name {
+        "alpha" => 'α', "beta" => 'β', "gamma" => 'γ', "delta" => 'δ', "epsilon" => 'ϵ',
+        "varepsilon" => 'ε', "zeta" => 'ζ', "eta" => 'η', "theta" => 'θ', "vartheta" => 'ϑ',
+        "iota" => 'ι', "kappa" => 'κ', "lambda" => 'λ', "mu" => 'μ', "nu" => 'ν', "xi" => 'ξ',
+        "pi" => 'π', "varpi" => 'ϖ', "rho" => 'ρ', "varrho" => 'ϱ', "sigma" => 'σ',
+        "varsigma" => 'ς', "tau" => 'τ', "upsilon" => 'υ', "phi" => 'ϕ', "varphi" => 'φ',
+        "chi" => 'χ', "psi" => 'ψ', "omega" => 'ω',
+        _ => return None,
+    })
+}
+
+fn upper_greek(name: &str) -> Option<char> {
+    Some(match name {
+        "Gamma" => 'Γ', "Delta" => 'Δ', "Theta" => 'Θ', "Lambda" => 'Λ', "Xi" => 'Ξ', "Pi" => 'Π',
+        "Sigma" => 'Σ', "Upsilon" => 'Υ', "Phi" => 'Φ', "Psi" => 'Ψ', "Omega" => 'Ω',
+        _ => return None,
+    })
+}
+
+// symbols that stand for a thing rather than an operation
+fn identifier(name: &str) -> Option<char> {
+    Some(match name {
+        "infty" => '∞', "partial" => '∂', "nabla" => '∇', "emptyset" => '∅', "varnothing" => '∅',
+        "hbar" => 'ℏ', "ell" => 'ℓ', "Re" => 'ℜ', "Im" => 'ℑ', "aleph" => 'ℵ', "wp" => '℘',
+        _ => return None,
+    })
+}
+
+fn operator(name: &str) -> Option<char> {
+    Some(match name {
+        "times" => '×', "cdot" => '⋅', "pm" => '±', "mp" => '∓', "div" => '÷', "ast" => '∗',
+        "star" => '⋆', "circ" => '∘', "bullet" => '∙', "oplus" => '⊕', "otimes" => '⊗',
+        "leq" | "le" => '≤', "geq" | "ge" => '≥', "neq" | "ne" => '≠', "approx" => '≈',
+        "equiv" => '≡', "sim" => '∼', "simeq" => '≃', "cong" => '≅', "propto" => '∝',
+        "ll" => '≪', "gg" => '≫', "prec" => '≺', "succ" => '≻',
+        "in" => '∈', "notin" => '∉', "ni" => '∋', "subset" => '⊂', "subseteq" => '⊆',
+        "supset" => '⊃', "supseteq" => '⊇', "cup" => '∪', "cap" => '∩', "setminus" => '∖',
+        "to" | "rightarrow" => '→', "leftarrow" | "gets" => '←', "Rightarrow" => '⇒',
+        "Leftarrow" => '⇐', "leftrightarrow" => '↔', "Leftrightarrow" => '⇔', "mapsto" => '↦',
+        "implies" => '⟹', "iff" => '⟺', "uparrow" => '↑', "downarrow" => '↓',
+        "longrightarrow" => '⟶', "longleftarrow" => '⟵',
+        "forall" => '∀', "exists" => '∃', "nexists" => '∄', "neg" | "lnot" => '¬',
+        "land" | "wedge" => '∧', "lor" | "vee" => '∨',
+        "ldots" | "dots" => '…', "cdots" => '⋯', "vdots" => '⋮', "ddots" => '⋱',
+        "langle" => '⟨', "rangle" => '⟩', "lfloor" => '⌊', "rfloor" => '⌋', "lceil" => '⌈',
+        "rceil" => '⌉', "lbrace" => '{', "rbrace" => '}', "vert" | "mid" => '|', "Vert" => '‖',
+        "parallel" => '∥', "perp" => '⊥', "angle" => '∠', "triangle" => '△', "colon" => ':',
+        "prime" => '′', "deg" => '°',
+        _ => return None,
+    })
+}
+
+// big operators, their scripts go above and below in display math
+fn big_operator(name: &str) -> Option<char> {
+    Some(match name {
+        "sum" => '∑', "prod" => '∏', "coprod" => '∐', "bigcup" => '⋃', "bigcap" => '⋂',
+        "bigoplus" => '⨁', "bigotimes" => '⨂', "bigvee" => '⋁', "bigwedge" => '⋀',
+        _ => return None,
+    })
+}
+
+// integrals are big too, but their limits stay to the side
+fn integral(name: &str) -> Option<char> {
+    Some(match name {
+        "int" => '∫', "iint" => '∬', "iiint" => '∭', "oint" => '∮',
+        _ => return None,
+    })
+}
+
+// upright names, the second part is whether limits go underneath
+fn function(name: &str) -> Option<bool> {
+    Some(match name {
+        "sin" | "cos" | "tan" | "cot" | "sec" | "csc" | "arcsin" | "arccos" | "arctan" | "sinh"
+        | "cosh" | "tanh" | "log" | "ln" | "lg" | "exp" | "dim" | "hom" | "ker" | "arg" => false,
+        "lim" | "liminf" | "limsup" | "max" | "min" | "sup" | "inf" | "det" | "gcd" | "Pr" => true,
+        _ => return None,
+    })
+}
+
+fn accent(name: &str) -> Option<char> {
+    Some(match name {
+        "hat" | "widehat" => '^', "bar" | "overline" => '‾', "vec" => '→', "dot" => '˙',
+        "ddot" => '¨', "tilde" | "widetilde" => '~', "check" => 'ˇ', "breve" => '˘',
+        "acute" => '´', "grave" => '`', "overrightarrow" => '→', "overleftarrow" => '←',
+        _ => return None,
+    })
+}
+
+fn font(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "mathrm" | "operatorname" => "normal",
+        "mathbf" | "boldsymbol" => "bold",
+        "mathit" => "italic",
+        "mathbb" => "double-struck",
+        "mathcal" => "script",
+        "mathfrak" => "fraktur",
+        "mathsf" => "sans-serif",
+        "mathtt" => "monospace",
+        _ => return None,
+    })
+}
+
+fn space(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "," => "0.167em", ":" | ">" => "0.222em", ";" => "0.278em", "!" => "-0.167em",
+        " " => "0.25em", "quad" => "1em", "qquad" => "2em",
+        _ => return None,
+    })
+}
+
+// \begin{...} names, and the brackets they put around the table
+fn environment(name: &str) -> Option<(&'static str, &'static str)> {
+    Some(match name {
+        "matrix" | "smallmatrix" | "array" | "aligned" | "align" | "align*" | "gathered" | "split" => ("", ""),
+        "pmatrix" => ("(", ")"),
+        "bmatrix" => ("[", "]"),
+        "Bmatrix" => ("{", "}"),
+        "vmatrix" => ("|", "|"),
+        "Vmatrix" => ("‖", "‖"),
+        "cases"   => ("{", ""),
+        _ => return None,
+    })
+}
+
+fn mo(c: impl std::fmt::Display) -> String {
+    format!("<mo>{}</mo>", esc(&c.to_string()))
+}
+
+fn fence(c: &str) -> String {
+    if c.is_empty() { String::new() } else { format!("<mo fence=\"true\" stretchy=\"true\">{}</mo>", esc(c)) }
+}
+
+impl Tex {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_space(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    // the command at the cursor without moving past it, for the things that end an expression
+    fn peek_command(&self) -> Option<String> {
+        if self.peek() != Some('\\') {
+            return None;
+        }
+        let name: String = self.chars[self.pos + 1..].iter().take_while(|c| c.is_ascii_alphabetic()).collect();
+        if name.is_empty() {
+            self.chars.get(self.pos + 1).map(|c| c.to_string())
+        } else {
+            Some(name)
+        }
+    }
+
+    fn command(&mut self) -> Result<String, String> {
+        let name = self.peek_command().ok_or("a \\ with nothing after it")?;
+        self.pos += 1 + name.chars().count();
+        Ok(name)
+    }
+
+    fn enter(&mut self) -> Result<(), String> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH { Err("nested too deeply".into()) } else { Ok(()) }
+    }
+
+    // a sequence of atoms up to whatever ends it, which is left for the caller to deal with
+    fn expr(&mut self) -> Result<(String, Stop), String> {
+        let mut out = String::new();
+        loop {
+            self.skip_space();
+            let stop = match self.peek() {
+                None      => Some(Stop::End),
+                Some('}') => Some(Stop::Brace),
+                Some('&') => { self.pos += 1; Some(Stop::Cell) }
+                Some('\\') => match self.peek_command().as_deref() {
+                    Some("\\")  => { self.pos += 2; Some(Stop::Row) }
+                    Some("right") => Some(Stop::Right),
+                    Some("end")   => Some(Stop::EndEnv),
+                    _             => None,
+                },
+                _ => None,
+            };
+            if let Some(stop) = stop {
+                return Ok((out, stop));
+            }
+            out.push_str(&self.scripted()?);
+        }
+    }
+
+    // an atom and any ^ and _ after it
+    fn scripted(&mut self) -> Result<String, String> {
+        let base = match self.peek() {
+            Some('^' | '_') => Node::new("<mrow></mrow>".into()),
+            _               => self.atom()?,
+        };
+        let (mut sup, mut sub) = (None, None);
+        loop {
+            self.skip_space();
+            let slot = match self.peek() {
+                Some('^') => &mut sup,
+                Some('_') => &mut sub,
+                // x' is x with a prime superscript
+                Some('\'') if sup.is_none() => {
+                    let mut primes = String::new();
+                    while self.peek() == Some('\'') {
+                        self.pos += 1;
+                        primes.push('′');
+                    }
+                    sup = Some(mo(primes));
+                    continue;
+                }
+                _ => break,
+            };
+            if slot.is_some() {
+                return Err("two superscripts or subscripts in a row, use braces".into());
+            }
+            self.pos += 1;
+            *slot = Some(self.argument()?);
+        }
+        let (under, over) = if base.limits { ("munder", "mover") } else { ("msub", "msup") };
+        let both = if base.limits { "munderover" } else { "msubsup" };
+        Ok(match (sub, sup) {
+            (None, None)           => base.xml,
+            (Some(b), None)        => format!("<{under}>{}{b}</{under}>", base.xml),
+            (None, Some(p))        => format!("<{over}>{}{p}</{over}>", base.xml),
+            (Some(b), Some(p))     => format!("<{both}>{}{b}{p}</{both}>", base.xml),
+        })
+    }
+
+    // what ^, _ and commands take: a braced group, a command, or a single character
+    fn argument(&mut self) -> Result<String, String> {
+        self.skip_space();
+        match self.peek() {
+            None              => Err("missing argument".into()),
+            Some('{' | '\\')  => Ok(self.atom()?.xml),
+            Some('}')         => Err("missing argument".into()),
+            Some(c)           => {
+                self.pos += 1;
+                Ok(if c.is_ascii_digit() { format!("<mn>{c}</mn>") } else if c.is_alphabetic() { format!("<mi>{c}</mi>") } else { mo(c) })
+            }
+        }
+    }
+
+    // the text of a {...} group as written, for \text and friends
+    fn raw_group(&mut self) -> Result<String, String> {
+        self.skip_space();
+        if self.peek() != Some('{') {
+            return Err("expected {".into());
+        }
+        self.pos += 1;
+        let mut depth = 1;
+        let mut text  = String::new();
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            match c {
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(text);
+                    }
+                }
+                _ => {}
+            }
+            text.push(c);
+        }
+        Err("missing }".into())
+    }
+
+    fn group(&mut self) -> Result<String, String> {
+        self.enter()?;
+        self.pos += 1;
+        let (body, stop) = self.expr()?;
+        if stop != Stop::Brace {
+            return Err("missing }".into());
+        }
+        self.pos += 1;
+        self.depth -= 1;
+        Ok(format!("<mrow>{body}</mrow>"))
+    }
+
+    // after \left or \right, "." means no bracket on that side
+    fn delimiter(&mut self) -> Result<String, String> {
+        self.skip_space();
+        match self.peek() {
+            Some('.')  => { self.pos += 1; Ok(String::new()) }
+            Some('\\') => {
+                let name = self.command()?;
+                match name.as_str() {
+                    "{" | "}" => Ok(name),
+                    "|"       => Ok("‖".into()),
+                    _         => operator(&name).map(String::from).ok_or(format!("\\{name} can't be a delimiter")),
+                }
+            }
+            Some(c) => { self.pos += 1; Ok(c.to_string()) }
+            None    => Err("missing delimiter".into()),
+        }
+    }
+
+    fn atom(&mut self) -> Result<Node, String> {
+        let c = self.peek().ok_or("missing argument")?;
+        if c == '{' {
+            return Ok(Node::new(self.group()?));
+        }
+        if c == '\\' {
+            return self.command_atom();
+        }
+        self.pos += 1;
+        if c.is_ascii_digit() || (c == '.' && self.peek().is_some_and(|n| n.is_ascii_digit())) {
+            let mut number = c.to_string();
+            while let Some(n) = self.peek().filter(|n| n.is_ascii_digit() || *n == '.') {
+                number.push(n);
+                self.pos += 1;
+            }
+            return Ok(Node::new(format!("<mn>{number}</mn>")));
+        }
+        if c.is_alphabetic() {
+            return Ok(Node::new(format!("<mi>{c}</mi>")));
+        }
+        Ok(Node::new(mo(c)))
+    }
+
+    fn command_atom(&mut self) -> Result<Node, String> {
+        let name = self.command()?;
+        let name = name.as_str();
+        if let Some(c) = greek(name) {
+            return Ok(Node::new(format!("<mi>{c}</mi>")));
+        }
+        if let Some(c) = upper_greek(name) {
+            return Ok(Node::new(format!("<mi mathvariant=\"normal\">{c}</mi>")));
+        }
+        if let Some(c) = identifier(name) {
+            return Ok(Node::new(format!("<mi>{c}</mi>")));
+        }
+        if let Some(c) = operator(name) {
+            return Ok(Node::new(mo(c)));
+        }
+        if let Some(c) = big_operator(name) {
+            return Ok(Node { xml: format!("<mo largeop=\"true\" movablelimits=\"true\">{c}</mo>"), limits: true });
+        }
+        if let Some(c) = integral(name) {
+            return Ok(Node::new(format!("<mo largeop=\"true\">{c}</mo>")));
+        }
+        if let Some(limits) = function(name) {
+            return Ok(Node { xml: format!("<mi>{name}</mi><mo>&#x2061;</mo>"), limits });
+        }
+        if let Some(width) = space(name) {
+            return Ok(Node::new(format!("<mspace width=\"{width}\"/>")));
+        }
+        if let Some(c) = accent(name) {
+            self.enter()?;
+            let base = self.argument()?;
+            self.depth -= 1;
+            return Ok(Node::new(format!("<mover accent=\"true\">{base}<mo stretchy=\"true\">{}</mo></mover>", esc(&c.to_string()))));
+        }
+        if let Some(variant) = font(name) {
+            let text = self.raw_group()?;
+            return Ok(Node::new(format!("<mi mathvariant=\"{variant}\">{}</mi>", esc(text.trim()))));
+        }
+        match name {
+            "{" | "}" | "%" | "$" | "&" | "#" | "_" => Ok(Node::new(mo(name))),
+            "|" => Ok(Node::new(mo('‖'))),
+            "displaystyle" | "textstyle" | "scriptstyle" | "limits" | "nolimits" => Ok(Node::new(String::new())),
+            "text" | "textrm" | "textit" | "textbf" | "mbox" => {
+                let text = self.raw_group()?;
+                Ok(Node::new(format!("<mtext>{}</mtext>", esc(&text))))
+            }
+            "frac" | "dfrac" | "tfrac" | "binom" => {
+                self.enter()?;
+                let top    = self.argument()?;
+                let bottom = self.argument()?;
+                self.depth -= 1;
+                Ok(Node::new(if name == "binom" {
+                    format!("<mrow><mo>(</mo><mfrac linethickness=\"0\">{top}{bottom}</mfrac><mo>)</mo></mrow>")
+                } else {
+                    format!("<mfrac>{top}{bottom}</mfrac>")
+                }))
+            }
+            "sqrt" => {
+                self.enter()?;
+                self.skip_space();
+                let index = if self.peek() == Some('[') {
+                    let close = self.chars[self.pos..].iter().position(|&c| c == ']').ok_or("missing ]")?;
+                    let inner: String = self.chars[self.pos + 1..self.pos + close].iter().collect();
+                    self.pos += close + 1;
+                    let mut sub = Tex { chars: inner.chars().collect(), pos: 0, depth: self.depth };
+                    let (index, stop) = sub.expr()?;
+                    if stop != Stop::End {
+                        return Err("unexpected } in root index".into());
+                    }
+                    Some(index)
+                } else {
+                    None
+                };
+                let body = self.argument()?;
+                self.depth -= 1;
+                Ok(Node::new(match index {
+                    Some(index) => format!("<mroot>{body}<mrow>{index}</mrow></mroot>"),
+                    None        => format!("<msqrt>{body}</msqrt>"),
+                }))
+            }
+            "underline" => {
+                self.enter()?;
+                let base = self.argument()?;
+                self.depth -= 1;
+                Ok(Node::new(format!("<munder accentunder=\"true\">{base}<mo stretchy=\"true\">_</mo></munder>")))
+            }
+            "left" => {
+                self.enter()?;
+                let open = self.delimiter()?;
+                let (body, stop) = self.expr()?;
+                if stop != Stop::Right {
+                    return Err("\\left without a \\right".into());
+                }
+                self.command()?;
+                let close = self.delimiter()?;
+                self.depth -= 1;
+                Ok(Node::new(format!("<mrow>{}{body}{}</mrow>", fence(&open), fence(&close))))
+            }
+            "begin" => self.environment(),
+            "right" => Err("\\right without a \\left".into()),
+            "end"   => Err("\\end without a \\begin".into()),
+            _       => Err(format!("unknown command \\{name}")),
+        }
+    }
+
+    fn environment(&mut self) -> Result<Node, String> {
+        self.enter()?;
+        let name = self.raw_group()?;
+        let (open, close) = environment(&name).ok_or(format!("unknown environment {name}"))?;
+        if name == "array" {
+            // column spec, alignment comes from mathml's defaults instead
+            self.raw_group()?;
+        }
+        let mut rows = vec![Vec::new()];
+        loop {
+            let (cell, stop) = self.expr()?;
+            rows.last_mut().unwrap().push(cell);
+            match stop {
+                Stop::Cell   => {}
+                Stop::Row    => rows.push(Vec::new()),
+                Stop::EndEnv => break,
+                Stop::End    => return Err(format!("missing \\end{{{name}}}")),
+                Stop::Brace  => return Err("unexpected }".into()),
+                Stop::Right  => return Err("\\right without a \\left".into()),
+            }
+        }
+        self.command()?;
+        let end = self.raw_group()?;
+        if end != name {
+            return Err(format!("\\begin{{{name}}} ended by \\end{{{end}}}"));
+        }
+        self.depth -= 1;
+        // a trailing \\ leaves an empty last row
+        if rows.len() > 1 && rows.last().is_some_and(|r| r.len() == 1 && r[0].is_empty()) {
+            rows.pop();
+        }
+        let align = match name.as_str() {
+            "cases"                                         => " columnalign=\"left\"",
+            "aligned" | "align" | "align*" | "split"        => " columnalign=\"right left\"",
+            _                                               => "",
+        };
+        let rows: String = rows.iter()
+            .map(|r| format!("<mtr>{}</mtr>", r.iter().map(|c| format!("<mtd><mrow>{c}</mrow></mtd>")).collect::<String>()))
+            .collect();
+        Ok(Node::new(format!("<mrow>{}<mtable{align}>{rows}</mtable>{}</mrow>", fence(open), fence(close))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inline(tex: &str) -> String {
+        latex_to_mathml(tex, false).unwrap()
+    }
+
+    #[test]
+    fn renders_scripts_and_fractions() {
+        assert_eq!(inline("x^2"), "<math><mrow><msup><mi>x</mi><mn>2</mn></msup></mrow></math>");
+        assert_eq!(
+            inline("a_{i}^{2}"),
+            "<math><mrow><msubsup><mi>a</mi><mrow><mi>i</mi></mrow><mrow><mn>2</mn></mrow></msubsup></mrow></math>"
+        );
+        assert_eq!(
+            inline("\\frac{1}{2}"),
+            "<math><mrow><mfrac><mrow><mn>1</mn></mrow><mrow><mn>2</mn></mrow></mfrac></mrow></math>"
+        );
+        assert_eq!(inline("\\sqrt[3]{x}"), "<math><mrow><mroot><mrow><mi>x</mi></mrow><mrow><mn>3</mn></mrow></mroot></mrow></math>");
+        assert_eq!(inline("f'"), "<math><mrow><msup><mi>f</mi><mo>′</mo></msup></mrow></math>");
+    }
+
+    #[test]
+    fn renders_symbols_and_big_operators() {
+        assert_eq!(inline("\\alpha \\leq \\Omega"), "<math><mrow><mi>α</mi><mo>≤</mo><mi mathvariant=\"normal\">Ω</mi></mrow></math>");
+        let sum = latex_to_mathml("\\sum_{i=1}^n i", true).unwrap();
+        assert!(sum.starts_with("<math display=\"block\"><mrow><munderover><mo largeop"), "{sum}");
+        assert!(inline("\\int_0^1").contains("<msubsup><mo largeop=\"true\">∫</mo>"));
+        assert!(inline("\\sin x").starts_with("<math><mrow><mi>sin</mi>"));
+    }
+
+    #[test]
+    fn renders_fences_text_and_matrices() {
+        assert_eq!(
+            inline("\\left( x \\right."),
+            "<math><mrow><mrow><mo fence=\"true\" stretchy=\"true\">(</mo><mi>x</mi></mrow></mrow></math>"
+        );
+        assert_eq!(inline("\\text{if } x"), "<math><mrow><mtext>if </mtext><mi>x</mi></mrow></math>");
+        let m = inline("\\begin{pmatrix} a & b \\\\ c & d \\end{pmatrix}");
+        assert!(m.contains("<mtable><mtr><mtd><mrow><mi>a</mi></mrow></mtd><mtd><mrow><mi>b</mi></mrow></mtd></mtr><mtr>"), "{m}");
+        assert!(m.starts_with("<math><mrow><mrow><mo fence=\"true\" stretchy=\"true\">(</mo>"), "{m}");
+    }
+
+    #[test]
+    fn reports_mistakes() {
+        assert_eq!(latex_to_mathml("\\frac{1}", false), Err("missing argument".into()));
+        assert_eq!(latex_to_mathml("{x", false), Err("missing }".into()));
+        assert_eq!(latex_to_mathml("x}", false), Err("unexpected }".into()));
+        assert_eq!(latex_to_mathml("\\foo", false), Err("unknown command \\foo".into()));
+        assert_eq!(latex_to_mathml("x^a^b", false), Err("two superscripts or subscripts in a row, use braces".into()));
+        assert_eq!(latex_to_mathml("\\left( x", false), Err("\\left without a \\right".into()));
+        assert!(latex_to_mathml("\\begin{matrix} a \\end{pmatrix}", false).is_err());
+        assert_eq!(latex_to_mathml(&"{".repeat(10_000), false), Err("nested too deeply".into()));
+        assert_eq!(latex_to_mathml(&"\\sqrt".repeat(10_000), false), Err("nested too deeply".into()));
+    }
+
+    #[test]
+    fn escapes_everything_it_copies() {
+        assert_eq!(inline("a<b"), "<math><mrow><mi>a</mi><mo>&lt;</mo><mi>b</mi></mrow></math>");
+        assert_eq!(inline("\\text{<script>}"), "<math><mrow><mtext>&lt;script&gt;</mtext></mrow></math>");
+        assert_eq!(inline("\\mathbf{\"x}"), "<math><mrow><mi mathvariant=\"bold\">&quot;x</mi></mrow></math>");
+        assert_eq!(
+            math_error("<img>", false, "unknown command \\<"),
+            "<span class=\"math-error\"><code>$&lt;img&gt;$</code> ⚠ unknown command \\&lt;</span>"
+        );
+    }
+}
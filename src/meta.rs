@@ -0,0 +1,174 @@
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+use reality::protocol::{Attachment, Role};
+
+// a named point in the doc's history, like "v1 approved"
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Tag {
+    pub name:    String,
+    pub heads:   Vec<String>,
+    pub created: i64,
+}
+
+// everything about a doc that isn't the text itself, lives next to it as docs/{id}.meta.json
+#[derive(Serialize, Deserialize, Clone)]
+pub struct DocMeta {
+    #[serde(default)]
+    pub tags: Vec<Tag>,
+    // automerge actor id -> display name of whoever made changes with it
+    #[serde(default)]
+    pub authors: BTreeMap<String, String>,
+    // secret for /view/:token, handed out lazily the first time someone asks for a view link
+    #[serde(default)]
+    pub view_token: Option<String>,
+    // user id of whoever owns the doc, None for docs made before accounts or by signed out people
+    #[serde(default)]
+    pub owner: Option<String>,
+    // user id -> role for people the owner shared with
+    #[serde(default)]
+    pub acl: BTreeMap<String, Role>,
+    // what knowing the /doc/:id link gets you, docs from before sharing existed stay editable by link
+    #[serde(default = "default_link_role")]
+    pub link_role: Option<Role>,
+    // argon2 hash, when set people coming in by link have to type the password first
+    #[serde(default)]
+    pub password_hash: Option<String>,
+    // files uploaded to the doc, the bytes are in docs/{id}.files/
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+    // for a doc someone claimed rather than made, what the link got people before the claim.
+    // the new owner can share it further but can't take that away
+    #[serde(default)]
+    pub link_floor: Option<Role>,
+}
+
+fn default_link_role() -> Option<Role> {
+    Some(Role::Editor)
+}
+
+impl Default for DocMeta {
+    fn default() -> Self {
+        Self {
+            tags:       Vec::new(),
+            authors:    BTreeMap::new(),
+            view_token: None,
+            owner:      None,
+            acl:        BTreeMap::new(),
+            link_role:  default_link_role(),
+            password_hash: None,
+            attachments: Vec::new(),
+            link_floor: None,
+        }
+    }
+}
+
+impl DocMeta {
+    pub fn load(id: &str) -> Self {
+        let path = format!("docs/{id}.meta.json");
+        match std::fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+                eprintln!("Failed to parse {path}: {e}");
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    // None means no access at all
+    pub fn role_for(&self, user_id: Option<&str>) -> Option<Role> {
+        if let Some(user_id) = user_id {
+            if self.owner.as_deref() == Some(user_id) {
+                return Some(Role::Owner);
+            }
+            if let Some(role) = self.acl.get(user_id) {
+                return Some((*role).max(self.link_role.unwrap_or(Role::Viewer)));
+            }
+        }
+        self.link_role
+    }
+
+    // docs nobody owns (from before accounts, or made while signed out) can be taken by a signed
+    // in editor. other people may have been working in it, so whatever the link got them it still does
+    pub fn claim(&mut self, user_id: &str) -> Result<(), &'static str> {
+        if self.owner.is_some() {
+            return Err("this doc already has an owner");
+        }
+        self.owner      = Some(user_id.to_string());
+        self.link_floor = self.link_role;
+        Ok(())
+    }
+
+    pub fn set_link_role(&mut self, role: Option<Role>) -> Result<(), &'static str> {
+        if role < self.link_floor {
+            return Err("people with the link had that access before this doc was claimed, it can't be taken away");
+        }
+        self.link_role = role;
+        Ok(())
+    }
+
+    // a password would shut out the people a claimed doc promised to keep letting in
+    pub fn can_lock(&self) -> bool {
+        self.link_floor.is_none()
+    }
+
+    // the owner and people it was shared with by name don't need the password
+    pub fn needs_password(&self, user_id: Option<&str>) -> bool {
+        if self.password_hash.is_none() {
+            return false;
+        }
+        match user_id {
+            Some(user_id) => self.owner.as_deref() != Some(user_id) && !self.acl.contains_key(user_id),
+            None          => true,
+        }
+    }
+
+    pub fn persist(&self, id: &str) {
+        let path = format!("docs/{id}.meta.json");
+        let json = serde_json::to_vec_pretty(self).unwrap();
+        tokio::spawn(async move {
+            if let Err(e) = std::fs::create_dir_all("docs") {
+                eprintln!("create_dir_all failed: {e}");
+                return;
+            }
+            if let Err(e) = std::fs::write(&path, &json) {
+                eprintln!("write {path} failed: {e}");
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn claiming_keeps_link_access() {
+        // a doc from before accounts, alice has been editing it through the link
+        let mut meta = DocMeta::default();
+        assert_eq!(meta.role_for(Some("alice")), Some(Role::Editor));
+
+        meta.claim("mallory").unwrap();
+        assert_eq!(meta.role_for(Some("mallory")), Some(Role::Owner));
+        assert_eq!(meta.claim("alice"), Err("this doc already has an owner"));
+        assert!(meta.set_link_role(None).is_err());
+        assert!(meta.set_link_role(Some(Role::Viewer)).is_err());
+        assert!(!meta.can_lock());
+        assert_eq!(meta.role_for(Some("alice")), Some(Role::Editor));
+        assert_eq!(meta.role_for(None), Some(Role::Editor));
+        assert!(!meta.needs_password(Some("alice")));
+
+        // sharing more is still fine
+        meta.acl.insert("bob".to_string(), Role::Editor);
+        assert_eq!(meta.set_link_role(Some(Role::Editor)), Ok(()));
+    }
+
+    #[test]
+    fn created_docs_can_be_closed() {
+        let mut meta = DocMeta { owner: Some("alice".to_string()), ..DocMeta::default() };
+        assert_eq!(meta.claim("mallory"), Err("this doc already has an owner"));
+        assert_eq!(meta.set_link_role(None), Ok(()));
+        assert!(meta.can_lock());
+        assert_eq!(meta.role_for(Some("mallory")), None);
+        assert_eq!(meta.role_for(Some("alice")), Some(Role::Owner));
+    }
+}
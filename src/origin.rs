@@ -0,0 +1,112 @@
+use std::sync::Arc;
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+use crate::state::AppState;
+
+// comma separated, e.g. REALITY_ALLOWED_ORIGINS="https://notes.example.com,http://localhost:8080".
+// "*" lets every site in, which is how things were before this existed, but without cookies.
+// a proxy that rewrites Host makes our own pages look cross site, list the public origin then
+const ORIGINS_ENV: &str = "REALITY_ALLOWED_ORIGINS";
+
+// sites other than our own that browsers may talk to us from
+#[derive(Clone, Default)]
+pub struct AllowedOrigins {
+    origins: Arc<Vec<String>>,
+}
+
+impl AllowedOrigins {
+    pub fn from_env() -> Self {
+        let origins: Vec<String> = std::env::var(ORIGINS_ENV)
+            .unwrap_or_default()
+            .split(',')
+            .map(|o| o.trim().trim_end_matches('/').to_string())
+            .filter(|o| !o.is_empty())
+            .collect();
+        if !origins.is_empty() {
+            println!("Allowing cross origin requests from: {}", origins.join(", "));
+        }
+        Self { origins: Arc::new(origins) }
+    }
+
+    fn any(&self) -> bool {
+        self.origins.iter().any(|o| o == "*")
+    }
+
+    fn allows(&self, headers: &HeaderMap) -> bool {
+        // browsers always send Origin on websocket upgrades and cross site requests,
+        // so no Origin means curl, a script, or a plain same site page load
+        let Some(origin) = headers.get(header::ORIGIN).and_then(|v| v.to_str().ok()) else {
+            return true;
+        };
+        if self.any() || self.origins.iter().any(|o| o == origin) {
+            return true;
+        }
+        // same origin, the page's host:port is the one the request was sent to. behind a
+        // proxy that changes Host this never matches, which is why the public origin can be listed
+        let host = headers.get(header::HOST).and_then(|v| v.to_str().ok());
+        origin.split_once("://").is_some_and(|(_, authority)| Some(authority) == host)
+    }
+
+    // cookies have to come along for sessions and doc passwords, so the origins are
+    // listed out rather than using a wildcard. with "*" any site can call us but only as
+    // nobody, otherwise every page on the web could act as whoever is signed in
+    pub fn cors(&self) -> CorsLayer {
+        let cors = if self.any() {
+            CorsLayer::new().allow_origin(AllowOrigin::any())
+        } else {
+            CorsLayer::new()
+                .allow_origin(AllowOrigin::list(self.origins.iter().filter_map(|o| HeaderValue::from_str(o).ok())))
+                .allow_credentials(true)
+        };
+        cors.allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
+            .allow_headers([header::CONTENT_TYPE, header::AUTHORIZATION])
+    }
+}
+
+// cors only stops a page reading responses, it does nothing for websockets or for
+// requests that change things, so anything from an origin we don't know is refused outright
+pub async fn check_origin(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    if !state.origins.allows(req.headers()) {
+        let origin = req.headers().get(header::ORIGIN).and_then(|v| v.to_str().ok()).unwrap_or("");
+        eprintln!("refused {} {} from origin {origin}", req.method(), req.uri().path());
+        return (StatusCode::FORBIDDEN, "origin not allowed").into_response();
+    }
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn origins(list: &[&str]) -> AllowedOrigins {
+        AllowedOrigins { origins: Arc::new(list.iter().map(|o| o.to_string()).collect()) }
+    }
+
+    fn request(origin: Option<&str>, host: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::HOST, host.parse().unwrap());
+        if let Some(origin) = origin {
+            headers.insert(header::ORIGIN, origin.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn only_known_origins_get_in() {
+        let listed = origins(&["https://notes.example.com"]);
+        assert!(listed.allows(&request(None, "reality.local:3001")));
+        assert!(listed.allows(&request(Some("https://notes.example.com"), "reality.local:3001")));
+        assert!(listed.allows(&request(Some("http://reality.local:3001"), "reality.local:3001")));
+        assert!(!listed.allows(&request(Some("https://evil.example"), "reality.local:3001")));
+        assert!(!listed.allows(&request(Some("https://notes.example.com.evil.example"), "reality.local:3001")));
+        // behind a proxy that rewrote Host, our own page only gets in once it's listed
+        assert!(!origins(&[]).allows(&request(Some("https://notes.example.com"), "127.0.0.1:3001")));
+        assert!(listed.allows(&request(Some("https://notes.example.com"), "127.0.0.1:3001")));
+        assert!(origins(&["*"]).allows(&request(Some("https://evil.example"), "reality.local:3001")));
+    }
+}
@@ -0,0 +1,199 @@
+use serde::{Deserialize, Serialize};
+
+// what a client sends over the socket, changes are automerge bytes from Doc::splice_text
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct ClientMsg {
+    pub client_id: String,
+    pub changes:   Vec<u8>,
+    // display name the client picked, used to put a name on its actor ids
+    #[serde(default)]
+    pub name:      Option<String>,
+}
+
+// what the server broadcasts, sender_id is "server" for the first full load after connecting
+// "error" when a change of ours was turned away, and "throttled" when ours are being held back
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct ServerMsg {
+    pub sender_id: String,
+    pub text:      String,
+    pub full_doc:  Vec<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error:     Option<String>,
+}
+
+impl ServerMsg {
+    pub fn error(message: impl Into<String>) -> Self {
+        Self {
+            sender_id: "error".to_string(),
+            text:      String::new(),
+            full_doc:  Vec::new(),
+            error:     Some(message.into()),
+        }
+    }
+
+    // nothing was lost, the change is saved once the wait is over
+    pub fn throttled(message: impl Into<String>) -> Self {
+        Self { sender_id: "throttled".to_string(), ..Self::error(message) }
+    }
+}
+
+// what someone may do with a doc, later variants can do everything earlier ones can
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Viewer,
+    Commenter,
+    Editor,
+    Owner,
+}
+
+impl Role {
+    pub fn can_edit(self) -> bool {
+        self >= Role::Editor
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Grant {
+    pub username: String,
+    pub role:     Role,
+}
+
+// the sharing dialog's view of a doc. owner, link_role and grants are only filled in for the owner,
+// link_role None means people with just the link get nothing
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+pub struct Sharing {
+    pub my_role:   Option<Role>,
+    pub owner:     Option<String>,
+    pub link_role: Option<Role>,
+    pub grants:    Vec<Grant>,
+    #[serde(default)]
+    pub has_password: bool,
+    // set on claimed docs, link_role can't go below it and no password can be added
+    #[serde(default)]
+    pub link_floor: Option<Role>,
+}
+
+// an uploaded file as the server stored it, named by the sha256 of its bytes so the same
+// screenshot pasted twice is kept once. url is what goes in the markdown
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Asset {
+    pub hash:         String,
+    pub url:          String,
+    pub content_type: String,
+    pub size:         usize,
+}
+
+// a file kept with one doc, it lives as long as the doc's text links to it
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Attachment {
+    pub id:      String,
+    pub name:    String,
+    pub size:    usize,
+    pub created: i64,
+    // when the text stopped linking to it, it's deleted a day after that unless the link comes back
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unreferenced_since: Option<i64>,
+}
+
+impl Attachment {
+    // the name is only there so the link reads well, the id is what finds the file
+    pub fn url(&self, doc_id: &str) -> String {
+        format!("/api/doc/{doc_id}/attachments/{}/{}", self.id, safe_file_name(&self.name))
+    }
+}
+
+// something that's fine in a url path and a Content-Disposition header
+pub fn safe_file_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || "-_.".contains(c) { c } else { '_' })
+        .collect();
+    match name.trim_start_matches('.') {
+        ""   => "file".to_string(),
+        name => name.to_string(),
+    }
+}
+
+// what an api token is allowed to do, read tokens never get past viewer and write tokens
+// never past editor, so sharing, passwords and ownership stay with people
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenScope {
+    Read,
+    Write,
+}
+
+impl TokenScope {
+    // the most a token with this scope can do, whatever its owner could
+    pub fn cap(self) -> Role {
+        match self {
+            TokenScope::Read  => Role::Viewer,
+            TokenScope::Write => Role::Editor,
+        }
+    }
+}
+
+// an api token as the token list shows it, the secret itself is only ever in NewToken
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct TokenInfo {
+    pub id:      String,
+    pub name:    String,
+    pub scope:   TokenScope,
+    // set for tokens that only work on one doc
+    pub doc_id:  Option<String>,
+    pub created: i64,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct NewToken {
+    pub token: String,
+    pub info:  TokenInfo,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn client_msg_name_is_optional() {
+        let msg: ClientMsg = serde_json::from_str(r#"{"client_id":"c1","changes":[1,2,3]}"#).unwrap();
+        assert_eq!(msg.name, None);
+        assert_eq!(msg.changes, [1, 2, 3]);
+    }
+
+    #[test]
+    fn roles_are_ordered() {
+        assert!(Role::Owner > Role::Editor);
+        assert!(!Role::Commenter.can_edit());
+        assert_eq!(serde_json::to_string(&Role::Viewer).unwrap(), "\"viewer\"");
+    }
+
+    #[test]
+    fn token_scopes_cap_roles() {
+        assert_eq!(TokenScope::Read.cap(), Role::Viewer);
+        assert!(!TokenScope::Read.cap().can_edit());
+        assert!(TokenScope::Write.cap().can_edit());
+        assert_eq!(TokenScope::Write.cap(), Role::Editor);
+        assert_eq!(serde_json::to_string(&TokenScope::Write).unwrap(), "\"write\"");
+    }
+
+    #[test]
+    fn server_msg_round_trips() {
+        let msg = ServerMsg { sender_id: "server".into(), text: "hi".into(), full_doc: vec![9], error: None };
+        let json = serde_json::to_string(&msg).unwrap();
+        assert!(!json.contains("error"));
+        assert_eq!(serde_json::from_str::<ServerMsg>(&json).unwrap(), msg);
+        let err = ServerMsg::error("too big");
+        assert_eq!(serde_json::from_str::<ServerMsg>(&serde_json::to_string(&err).unwrap()).unwrap(), err);
+    }
+
+    #[test]
+    fn attachment_urls_are_path_safe() {
+        let a = Attachment { id: "abc".into(), name: "Q3 report (final).pdf".into(), size: 1, created: 0, unreferenced_since: None };
+        assert_eq!(a.url("notes"), "/api/doc/notes/attachments/abc/Q3_report__final_.pdf");
+        assert_eq!(safe_file_name("../../etc/passwd"), "_.._etc_passwd");
+        assert_eq!(safe_file_name("..."), "file");
+        assert!(!serde_json::to_string(&a).unwrap().contains("unreferenced_since"));
+    }
+}
@@ -0,0 +1,93 @@
+use std::hash::{Hash, Hasher};
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderMap, StatusCode},
+    response::IntoResponse,
+    routing::get,
+    Router,
+};
+use reality::highlight::{theme_css, DEFAULT_THEME};
+use reality::markdown::{headings, html_escape, render_markdown_with, RenderOptions, MARKDOWN_STYLE};
+use reality::protocol::Role;
+use crate::api::authorize;
+use crate::auth::Caller;
+use crate::state::AppState;
+
+// /p/:id, the doc as a normal html page so people without wasm (and link previews) can read it
+pub fn publish_router(state: AppState) -> Router {
+    Router::new()
+        .route("/p/:id", get(published_page))
+        .with_state(state)
+}
+
+const PAGE_STYLE: &str = "body{max-width:800px;margin:0 auto;padding:2rem;font-family:sans-serif;line-height:1.6;color:#222;}\
+footer{margin-top:3rem;font-size:0.8rem;opacity:0.6;}";
+
+// first markdown heading wins, otherwise just call it by its id
+fn page_title(md: &str, id: &str) -> String {
+    headings(md)
+        .into_iter()
+        .map(|h| h.text)
+        .find(|t| !t.is_empty())
+        .unwrap_or_else(|| format!("Reality · {id}"))
+}
+
+// heads change on every edit and only on an edit, so they make a good etag
+fn etag_for(heads: &[String]) -> String {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    heads.hash(&mut hasher);
+    format!("\"{:016x}\"", hasher.finish())
+}
+
+async fn published_page(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    caller: Caller,
+) -> impl IntoResponse {
+    let room = match authorize(&state, &id, &caller, Role::Viewer).await {
+        Ok(room) => room,
+        Err(resp) => return resp,
+    };
+    let (text, heads) = {
+        let mut doc = room.doc.lock().await;
+        (doc.get_text(), doc.heads())
+    };
+    let etag = etag_for(&heads);
+    let cache = [
+        (header::ETAG, etag.clone()),
+        (header::CACHE_CONTROL, "no-cache".to_string()),
+    ];
+    if headers.get(header::IF_NONE_MATCH).and_then(|v| v.to_str().ok()) == Some(etag.as_str()) {
+        return (StatusCode::NOT_MODIFIED, cache).into_response();
+    }
+
+    let title = html_escape(&page_title(&text, &id));
+    let body  = render_markdown_with(&text, RenderOptions { highlight_code: true, ..Default::default() });
+    let code  = theme_css(DEFAULT_THEME);
+    let page = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>{title}</title>\n<meta property=\"og:title\" content=\"{title}\">\n\
+         <style>{PAGE_STYLE}{MARKDOWN_STYLE}{code}</style>\n</head>\n<body>\n<article class=\"markdown\">\n{body}</article>\n\
+         <footer>Published with Reality</footer>\n</body>\n</html>\n"
+    );
+    (
+        cache,
+        [(header::CONTENT_TYPE, "text/html; charset=utf-8")],
+        page,
+    ).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn title_is_the_first_real_heading() {
+        assert_eq!(page_title("intro\n\n## Plan *v2*\n# Later", "notes"), "Plan v2");
+        assert_eq!(page_title("```bash\n# install it\n```\n# Setup", "notes"), "Setup");
+        assert_eq!(page_title("    # indented code\n\nMeeting notes\n===\n", "notes"), "Meeting notes");
+        assert_eq!(page_title("#hashtag\n#\n", "notes"), "Reality · notes");
+    }
+}
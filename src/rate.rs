@@ -0,0 +1,291 @@
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use dashmap::DashMap;
+use crate::limits::Limits;
+
+// classic token bucket, except taking more than is there goes into debt and tells you how
+// long to wait, so messages queue up behind each other instead of being dropped
+pub struct TokenBucket {
+    capacity: f64,
+    per_sec:  f64,
+    tokens:   f64,
+    last:     Instant,
+}
+
+impl TokenBucket {
+    // starts full, burst is how much can go through at once after being idle
+    pub fn new(per_sec: f64, burst: f64) -> Self {
+        Self { capacity: burst, per_sec, tokens: burst, last: Instant::now() }
+    }
+
+    pub fn take(&mut self, amount: f64) -> Duration {
+        self.take_at(amount, Instant::now())
+    }
+
+    fn take_at(&mut self, amount: f64, now: Instant) -> Duration {
+        let elapsed = now.duration_since(self.last).as_secs_f64();
+        self.last = now;
+        self.tokens = (self.tokens + elapsed * self.per_sec).min(self.capacity) - amount;
+        if self.tokens >= 0.0 || self.per_sec <= 0.0 {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64(-self.tokens / self.per_sec)
+    }
+
+    fn give_back(&mut self, amount: f64) {
+        self.tokens = (self.tokens + amount).min(self.capacity);
+    }
+
+    // topped back up by now, so forgetting it changes nothing
+    fn is_full(&self) -> bool {
+        self.tokens + self.last.elapsed().as_secs_f64() * self.per_sec >= self.capacity
+    }
+}
+
+// a message count bucket and a byte bucket, whichever is further behind decides the wait
+pub struct Throttle {
+    messages: TokenBucket,
+    bytes:    TokenBucket,
+}
+
+impl Throttle {
+    // a few seconds of burst, and never less than one max size frame of bytes
+    fn new(messages_per_sec: usize, bytes_per_sec: usize, limits: &Limits) -> Self {
+        let burst_bytes = (bytes_per_sec * 3).max(limits.max_frame_bytes);
+        Self {
+            messages: TokenBucket::new(messages_per_sec as f64, (messages_per_sec * 3) as f64),
+            bytes:    TokenBucket::new(bytes_per_sec as f64, burst_bytes as f64),
+        }
+    }
+
+    pub fn per_socket(limits: &Limits) -> Self {
+        Self::new(limits.socket_messages_per_sec, limits.socket_bytes_per_sec, limits)
+    }
+
+    pub fn take(&mut self, len: usize) -> Duration {
+        self.messages.take(1.0).max(self.bytes.take(len as f64))
+    }
+}
+
+struct Client {
+    throttle: Arc<Mutex<Throttle>>,
+    sockets:  usize,
+    last:     Instant,
+}
+
+// everything one address is doing across all its sockets
+#[derive(Clone, Default)]
+pub struct RateLimiter {
+    clients: Arc<DashMap<IpAddr, Client>>,
+}
+
+// held for as long as a socket is open, gives the slot back when dropped
+pub struct SocketSlot {
+    ip:       IpAddr,
+    clients:  Arc<DashMap<IpAddr, Client>>,
+    throttle: Arc<Mutex<Throttle>>,
+}
+
+impl SocketSlot {
+    // shared by every socket from the same address
+    pub fn take(&self, len: usize) -> Duration {
+        self.throttle.lock().unwrap().take(len)
+    }
+}
+
+impl Drop for SocketSlot {
+    fn drop(&mut self) {
+        if let Some(mut client) = self.clients.get_mut(&self.ip) {
+            client.sockets = client.sockets.saturating_sub(1);
+            client.last = Instant::now();
+        }
+    }
+}
+
+// an address that's gone quiet for this long starts over with full buckets
+const IDLE_FORGET: Duration = Duration::from_secs(60);
+
+impl RateLimiter {
+    // None when this address already has as many sockets open as it's allowed
+    pub fn open_socket(&self, ip: IpAddr, limits: &Limits) -> Option<SocketSlot> {
+        self.clients.retain(|_, c| c.sockets > 0 || c.last.elapsed() < IDLE_FORGET);
+        let mut client = self.clients.entry(ip).or_insert_with(|| Client {
+            throttle: Arc::new(Mutex::new(Throttle::new(limits.ip_messages_per_sec, limits.ip_bytes_per_sec, limits))),
+            sockets:  0,
+            last:     Instant::now(),
+        });
+        if client.sockets >= limits.max_sockets_per_ip {
+            return None;
+        }
+        client.sockets += 1;
+        client.last = Instant::now();
+        Some(SocketSlot { ip, clients: self.clients.clone(), throttle: client.throttle.clone() })
+    }
+}
+
+// a few wrong passwords go through at once for typos, then one every so often
+const IP_GUESSES:     (f64, f64) = (1.0 / 10.0, 10.0);
+// shared by everyone trying the same doc or username, so spreading guesses over many addresses
+// doesn't get far either
+const TARGET_GUESSES: (f64, f64) = (1.0 / 5.0, 20.0);
+
+// wrong password tries, by address and by what's being guessed at
+#[derive(Clone, Default)]
+pub struct Guesses {
+    by_ip:     Arc<DashMap<IpAddr, TokenBucket>>,
+    by_target: Arc<DashMap<String, TokenBucket>>,
+}
+
+impl Guesses {
+    // called before checking a password. a guess is taken up front so a pile of tries sent at
+    // once can't all get in before the first one fails, Err is how long until the next is allowed
+    pub fn take(&self, ip: IpAddr, target: &str) -> Result<(), Duration> {
+        self.by_ip.retain(|_, b| !b.is_full());
+        self.by_target.retain(|_, b| !b.is_full());
+        let (per_sec, burst) = IP_GUESSES;
+        let ip_wait = self.by_ip.entry(ip).or_insert_with(|| TokenBucket::new(per_sec, burst)).take(1.0);
+        let (per_sec, burst) = TARGET_GUESSES;
+        let target_wait = self.by_target.entry(target.to_string()).or_insert_with(|| TokenBucket::new(per_sec, burst)).take(1.0);
+        let wait = ip_wait.max(target_wait);
+        if wait > Duration::ZERO {
+            // turned away without a check, so it doesn't count
+            self.right(ip, target);
+            return Err(wait);
+        }
+        Ok(())
+    }
+
+    // only wrong passwords count, a right one gets its guess back
+    pub fn right(&self, ip: IpAddr, target: &str) {
+        if let Some(mut bucket) = self.by_ip.get_mut(&ip) {
+            bucket.give_back(1.0);
+        }
+        if let Some(mut bucket) = self.by_target.get_mut(target) {
+            bucket.give_back(1.0);
+        }
+    }
+}
+
+pub fn too_many_guesses(wait: Duration) -> Response {
+    let secs = wait.as_secs_f64().ceil() as u64;
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(header::RETRY_AFTER, secs.to_string())],
+        format!("too many wrong passwords, try again in {secs} seconds"),
+    ).into_response()
+}
+
+// the peer address, or when we're told a proxy sits in front, the last X-Forwarded-For entry.
+// that's the one our proxy added, anything before it came from the client and could be made up
+pub fn client_ip(addr: SocketAddr, headers: &HeaderMap, limits: &Limits) -> IpAddr {
+    if limits.trust_forwarded_for {
+        let forwarded = headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .next_back()
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.rsplit(',').next())
+            .and_then(|ip| ip.trim().parse().ok());
+        if let Some(ip) = forwarded {
+            return ip;
+        }
+    }
+    addr.ip()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets_burst_then_refill() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket { last: start, ..TokenBucket::new(10.0, 3.0) };
+        for _ in 0..3 {
+            assert_eq!(bucket.take_at(1.0, start), Duration::ZERO);
+        }
+        // in debt by one, which takes a tenth of a second to pay back
+        assert_eq!(bucket.take_at(1.0, start), Duration::from_millis(100));
+        // a second later it's full again, but never more than the burst
+        let later = start + Duration::from_secs(1);
+        assert_eq!(bucket.take_at(3.0, later), Duration::ZERO);
+        assert!(bucket.take_at(1.0, later) > Duration::ZERO);
+    }
+
+    #[test]
+    fn throttle_waits_on_whichever_is_further_behind() {
+        let limits = Limits { socket_messages_per_sec: 1000, socket_bytes_per_sec: 10, max_frame_bytes: 10, ..Limits::default() };
+        let mut throttle = Throttle::per_socket(&limits);
+        assert_eq!(throttle.take(30), Duration::ZERO);
+        let wait = throttle.take(10);
+        assert!(wait > Duration::from_millis(900) && wait <= Duration::from_secs(1), "{wait:?}");
+    }
+
+    #[test]
+    fn sockets_per_ip_are_capped_and_given_back() {
+        let limits  = Limits { max_sockets_per_ip: 2, ..Limits::default() };
+        let limiter = RateLimiter::default();
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let first  = limiter.open_socket(ip, &limits).unwrap();
+        let second = limiter.open_socket(ip, &limits).unwrap();
+        assert!(limiter.open_socket(ip, &limits).is_none());
+        // someone else isn't affected
+        assert!(limiter.open_socket("10.0.0.2".parse().unwrap(), &limits).is_some());
+        drop(first);
+        let third = limiter.open_socket(ip, &limits).unwrap();
+        assert!(limiter.open_socket(ip, &limits).is_none());
+        drop((second, third));
+        assert!(limiter.open_socket(ip, &limits).is_some());
+    }
+
+    #[test]
+    fn wrong_passwords_run_out_per_address_and_per_target() {
+        let guesses = Guesses::default();
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let (_, ip_burst) = IP_GUESSES;
+        for _ in 0..ip_burst as usize {
+            assert!(guesses.take(ip, "doc:a").is_ok());
+        }
+        let wait = guesses.take(ip, "doc:b").unwrap_err();
+        assert!(wait > Duration::from_secs(9) && wait <= Duration::from_secs(10), "{wait:?}");
+        // being turned away doesn't dig the hole deeper, and a right password gives its guess back
+        assert!(guesses.take(ip, "doc:b").unwrap_err() <= wait);
+        guesses.right(ip, "doc:a");
+        assert!(guesses.take(ip, "doc:a").is_ok());
+
+        // other addresses can keep going until the doc itself runs out
+        let (_, target_burst) = TARGET_GUESSES;
+        let mut tried = ip_burst as usize;
+        for n in 2.. {
+            if guesses.take(format!("10.0.0.{n}").parse().unwrap(), "doc:a").is_err() {
+                break;
+            }
+            tried += 1;
+        }
+        assert_eq!(tried, target_burst as usize);
+        assert!(guesses.take("10.0.1.1".parse().unwrap(), "doc:b").is_ok());
+
+        let resp = too_many_guesses(Duration::from_millis(4200));
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(resp.headers()[header::RETRY_AFTER], "5");
+    }
+
+    #[test]
+    fn forwarded_for_uses_the_entry_our_proxy_added() {
+        let peer: SocketAddr = "127.0.0.1:5000".parse().unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", "6.6.6.6, 203.0.113.7".parse().unwrap());
+        let trusting = Limits { trust_forwarded_for: true, ..Limits::default() };
+        assert_eq!(client_ip(peer, &headers, &trusting), "203.0.113.7".parse::<IpAddr>().unwrap());
+        assert_eq!(client_ip(peer, &headers, &Limits::default()), peer.ip());
+
+        headers.append("x-forwarded-for", "198.51.100.2".parse().unwrap());
+        assert_eq!(client_ip(peer, &headers, &trusting), "198.51.100.2".parse::<IpAddr>().unwrap());
+
+        headers.insert("x-forwarded-for", "not an ip".parse().unwrap());
+        assert_eq!(client_ip(peer, &headers, &trusting), peer.ip());
+    }
+}
@@ -1,24 +1,54 @@
-mod crdt;
+mod api;
+mod assets;
+mod attachments;
+mod api_tokens;
+mod auth;
+mod limits;
+mod lock;
+mod meta;
+mod origin;
+mod publish;
+mod rate;
 mod state;
 mod ws;
 
-use axum::Router;
-use tower_http::{cors::CorsLayer, services::{ServeDir, ServeFile}};
+use std::net::SocketAddr;
+use axum::{middleware, Router};
+use tower_http::services::{ServeDir, ServeFile};
 
 #[tokio::main]
 async fn main() {
     let state = state::AppState::new();
-    let ws_routes = ws::ws_router(state);
+    let cors = state.origins.cors();
+    let origin_check = middleware::from_fn_with_state(state.clone(), origin::check_origin);
+    let ws_routes = ws::ws_router(state.clone());
+    let api_routes = api::api_router(state.clone());
+    let auth_routes = auth::auth_router(state.clone());
+    let lock_routes = lock::lock_router(state.clone());
+    let token_routes = api_tokens::tokens_router(state.clone());
+    let attachment_routes = attachments::attachments_router(state.clone());
+    attachments::spawn_gc(state.clone());
 
     let serve_dir = ServeDir::new("target/dx/reality/release/web/public")
         .fallback(ServeFile::new("target/dx/reality/release/web/public/index.html")); //allows full code urls to work, no idea why
+    let asset_routes = assets::assets_router(state.clone(), serve_dir.clone());
+    let publish_routes = publish::publish_router(state);
 
     let app = Router::new()
         .merge(ws_routes)
+        .merge(api_routes)
+        .merge(auth_routes)
+        .merge(lock_routes)
+        .merge(token_routes)
+        .merge(asset_routes)
+        .merge(attachment_routes)
+        .merge(publish_routes)
         .fallback_service(serve_dir)
-        .layer(CorsLayer::permissive());
+        .layer(origin_check)
+        .layer(cors);
 
     let listener = tokio::net::TcpListener::bind("0.0.0.0:3001").await.unwrap();
     println!("Reality running on http://0.0.0.0:3001");
-    axum::serve(listener, app).await.unwrap();
+    // the peer address is what sockets get rate limited by
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await.unwrap();
 }
\ No newline at end of file
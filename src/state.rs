@@ -1,12 +1,23 @@
 use dashmap::DashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use tokio::sync::{broadcast, Mutex};
-use crate::crdt::Doc;
+use reality::doc::Doc;
+use crate::api_tokens::ApiTokens;
+use crate::auth::{Accounts, AuthUser};
+use crate::limits::Limits;
+use crate::lock::DocPasses;
+use crate::meta::DocMeta;
+use crate::origin::AllowedOrigins;
+use crate::rate::{Guesses, RateLimiter};
 
 #[derive(Clone)]
 pub struct Room {
-    pub doc: Arc<Mutex<Doc>>,
-    pub tx:  broadcast::Sender<String>,
+    pub doc:  Arc<Mutex<Doc>>,
+    pub meta: Arc<Mutex<DocMeta>>,
+    pub tx:   broadcast::Sender<String>,
+    // bytes in the .am as of the last save, which is what the history limit is checked against
+    pub saved_len: Arc<AtomicUsize>,
 }
 
 impl Room {
@@ -14,20 +25,46 @@ pub fn new(initial_text: &str) -> Self {
     let (tx, _) = broadcast::channel(64);
     let mut doc = Doc::new();
     if !initial_text.is_empty() {
-        doc.splice_text(0, 0, initial_text);
+        doc.splice_text(0, 0, initial_text, now_secs());
     }
-    Self { doc: Arc::new(Mutex::new(doc)), tx }
+    Self { doc: Arc::new(Mutex::new(doc)), meta: Arc::default(), tx, saved_len: Arc::default() }
     }
     pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
         let (tx, _) = broadcast::channel(64);
         let doc = Doc::load_from_bytes(bytes)?;
-        Some(Self { doc: Arc::new(Mutex::new(doc)), tx })
+        let saved_len = Arc::new(AtomicUsize::new(bytes.len()));
+        Some(Self { doc: Arc::new(Mutex::new(doc)), meta: Arc::default(), tx, saved_len })
+    }
+
+    pub fn with_meta(mut self, meta: DocMeta) -> Self {
+        self.meta = Arc::new(Mutex::new(meta));
+        self
+    }
+
+    pub fn persist(&self, id: &str, text: String, full_doc: Vec<u8>) {
+        self.saved_len.store(full_doc.len(), Ordering::Relaxed);
+        persist_doc(id, text, full_doc);
+    }
+
+    // once the history is this big nobody gets to add to it
+    pub fn history_full(&self, limits: &Limits) -> bool {
+        self.saved_len.load(Ordering::Relaxed) >= limits.max_history_bytes
     }
 }
 
 #[derive(Clone)]
 pub struct AppState {
     pub rooms: Arc<DashMap<String, Room>>,
+    // view only token -> doc id
+    pub view_tokens: Arc<DashMap<String, String>>,
+    pub accounts: Accounts,
+    pub passes: DocPasses,
+    pub tokens: ApiTokens,
+    pub origins: AllowedOrigins,
+    pub limits: Limits,
+    pub rates: RateLimiter,
+    // wrong passwords, for doc locks and logins
+    pub guesses: Guesses,
 }
 
 impl AppState {
@@ -46,7 +83,7 @@ impl AppState {
                                         match Room::from_bytes(&bytes) {
                                             Some(room) => {
                                                 println!("Loaded doc (binary): {stem}");
-                                                rooms.insert(stem.to_string(), room);
+                                                rooms.insert(stem.to_string(), room.with_meta(DocMeta::load(stem)));
                                             }
                                             None => eprintln!("Failed to parse AM file: {:?}", path),
                                         }
@@ -61,15 +98,8 @@ impl AppState {
                                     match std::fs::read_to_string(&path) {
                                         Ok(content) => {
                                             println!("Loaded doc (text legacy): {stem}");
-                                            let mut doc = Doc::new();
-                                            if !content.is_empty() {
-                                                doc.splice_text(0, 0, &content);
-                                            }
-                                            let (tx, _) = broadcast::channel(64);
-                                            rooms.insert(stem.to_string(), Room {
-                                                doc: Arc::new(Mutex::new(doc)),
-                                                tx,
-                                            });
+                                            let room = Room::new(&content).with_meta(DocMeta::load(stem));
+                                            rooms.insert(stem.to_string(), room);
                                         }
                                         Err(e) => eprintln!("Failed to read {:?}: {e}", path),
                                     }
@@ -86,13 +116,98 @@ impl AppState {
             }
         }
 
-        Self { rooms }
+        let view_tokens: Arc<DashMap<String, String>> = Arc::new(DashMap::new());
+        for room in rooms.iter() {
+            if let Some(token) = room.meta.try_lock().ok().and_then(|m| m.view_token.clone()) {
+                view_tokens.insert(token, room.key().clone());
+            }
+        }
+
+        Self { rooms, view_tokens, accounts: Accounts::load(), passes: DocPasses::default(), tokens: ApiTokens::load(), origins: AllowedOrigins::from_env(), limits: Limits::from_env(), rates: RateLimiter::default(), guesses: Guesses::default() }
+    }
+
+    pub fn get_room(&self, doc_id: &str) -> Option<Room> {
+        self.rooms.get(doc_id).map(|r| r.clone())
     }
 
-    pub fn get_or_create_room(&self, doc_id: &str) -> Room {
+    pub fn doc_for_view_token(&self, token: &str) -> Option<String> {
+        self.view_tokens.get(token).map(|id| id.clone())
+    }
+
+    // existing token if the doc has one, otherwise mints and saves a new one
+    pub async fn view_token(&self, doc_id: &str, room: &Room) -> String {
+        let mut meta = room.meta.lock().await;
+        if let Some(token) = &meta.view_token {
+            return token.clone();
+        }
+        let token = uuid::Uuid::new_v4().simple().to_string();
+        meta.view_token = Some(token.clone());
+        meta.persist(doc_id);
+        self.view_tokens.insert(token.clone(), doc_id.to_string());
+        token
+    }
+
+    // a signed in creator becomes the owner, and the empty doc is written out
+    // straight away so the ownership survives a restart
+    pub fn get_or_create_room(&self, doc_id: &str, creator: Option<&AuthUser>) -> Room {
         self.rooms
             .entry(doc_id.to_string())
-            .or_insert_with(|| Room::new(""))
+            .or_insert_with(|| {
+                let room = Room::new("");
+                if let Some(user) = creator {
+                    let meta = DocMeta { owner: Some(user.id.clone()), ..DocMeta::default() };
+                    meta.persist(doc_id);
+                    if let Ok(mut doc) = room.doc.try_lock() {
+                        room.persist(doc_id, doc.get_text(), doc.save());
+                    }
+                    return room.with_meta(meta);
+                }
+                room
+            })
             .clone()
     }
 }
+
+#[cfg(test)]
+impl AppState {
+    // nothing loaded from or written to disk
+    pub fn in_memory() -> Self {
+        Self {
+            rooms:       Arc::default(),
+            view_tokens: Arc::default(),
+            accounts:    Accounts::default(),
+            passes:      DocPasses::default(),
+            tokens:      ApiTokens::default(),
+            origins:     AllowedOrigins::default(),
+            limits:      Limits::default(),
+            rates:       RateLimiter::default(),
+            guesses:     Guesses::default(),
+        }
+    }
+}
+
+pub fn now_secs() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+// writes the readable .md and the full automerge .am off the async path so sockets don't wait on disk
+pub fn persist_doc(id: &str, text: String, full_doc: Vec<u8>) {
+    let id = id.to_string();
+    tokio::spawn(async move {
+        if let Err(e) = std::fs::create_dir_all("docs") {
+            eprintln!("create_dir_all failed: {e}");
+            return;
+        }
+        let path = format!("docs/{id}.md");
+        if let Err(e) = std::fs::write(&path, &text) {
+            eprintln!("write {path} failed: {e}");
+        }
+        let am_path = format!("docs/{id}.am");
+        if let Err(e) = std::fs::write(&am_path, &full_doc) {
+            eprintln!("write {am_path} failed: {e}");
+        }
+    });
+}
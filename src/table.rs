@@ -0,0 +1,234 @@
+// editing github style pipe tables in the source, every edit also lines the pipes back up
+// so the table stays readable in the textarea. positions are char indices like everywhere else
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TableEdit {
+    AddRow,
+    RemoveRow,
+    AddColumn,
+    RemoveColumn,
+    Align,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Align {
+    None,
+    Left,
+    Center,
+    Right,
+}
+
+// what the toolbar inserts, a header and one empty row
+pub const NEW_TABLE: &str = "| Column 1 | Column 2 | Column 3 |\n| -------- | -------- | -------- |\n|          |          |          |";
+
+// the narrowest a column gets, so the delimiter row always has its three dashes
+const MIN_WIDTH: usize = 3;
+
+struct Table {
+    // header first, the delimiter row isn't kept, its alignments are
+    rows:   Vec<Vec<String>>,
+    aligns: Vec<Align>,
+}
+
+// cells of one row, pipes escaped with a backslash stay in the cell
+fn split_row(line: &str) -> Vec<String> {
+    let line = line.trim();
+    let line = line.strip_prefix('|').unwrap_or(line);
+    let line = if line.ends_with('|') && !line.ends_with("\\|") { &line[..line.len() - 1] } else { line };
+    let mut cells   = Vec::new();
+    let mut current = String::new();
+    let mut escaped = false;
+    for c in line.chars() {
+        if c == '|' && !escaped {
+            cells.push(current.trim().to_string());
+            current.clear();
+        } else {
+            current.push(c);
+        }
+        escaped = c == '\\' && !escaped;
+    }
+    cells.push(current.trim().to_string());
+    cells
+}
+
+fn parse_align(cell: &str) -> Option<Align> {
+    let dashes = cell.trim_start_matches(':').trim_end_matches(':');
+    if dashes.is_empty() || !dashes.chars().all(|c| c == '-') {
+        return None;
+    }
+    Some(match (cell.starts_with(':'), cell.ends_with(':')) {
+        (true, true)   => Align::Center,
+        (true, false)  => Align::Left,
+        (false, true)  => Align::Right,
+        (false, false) => Align::None,
+    })
+}
+
+fn parse_table(lines: &[&str]) -> Option<Table> {
+    let aligns: Vec<Align> = split_row(lines.get(1)?).iter().map(|c| parse_align(c)).collect::<Option<_>>()?;
+    let mut rows: Vec<Vec<String>> = lines.iter().enumerate().filter(|(i, _)| *i != 1).map(|(_, l)| split_row(l)).collect();
+    // ragged rows get filled out rather than cut, nobody's text goes missing
+    let cols = rows.iter().map(Vec::len).max().unwrap_or(0).max(aligns.len());
+    for row in &mut rows {
+        row.resize(cols, String::new());
+    }
+    let mut aligns = aligns;
+    aligns.resize(cols, Align::None);
+    Some(Table { rows, aligns })
+}
+
+impl Table {
+    fn widths(&self) -> Vec<usize> {
+        (0..self.aligns.len())
+            .map(|c| self.rows.iter().map(|r| r[c].chars().count()).max().unwrap_or(0).max(MIN_WIDTH))
+            .collect()
+    }
+
+    // lines of source, and where each cell's text starts within its line
+    fn format(&self) -> (Vec<String>, Vec<usize>) {
+        let widths = self.widths();
+        let starts = widths.iter().scan(2, |at, w| { let start = *at; *at += w + 3; Some(start) }).collect();
+        let row = |cells: &[String]| {
+            let padded: Vec<String> = cells.iter().zip(&widths).zip(&self.aligns).map(|((cell, &w), align)| {
+                let gap = w - cell.chars().count();
+                match align {
+                    Align::Right  => format!("{}{cell}", " ".repeat(gap)),
+                    Align::Center => format!("{}{cell}{}", " ".repeat(gap / 2), " ".repeat(gap - gap / 2)),
+                    _             => format!("{cell}{}", " ".repeat(gap)),
+                }
+            }).collect();
+            format!("| {} |", padded.join(" | "))
+        };
+        let delimiter: Vec<String> = widths.iter().zip(&self.aligns).map(|(&w, align)| match align {
+            Align::None   => "-".repeat(w),
+            Align::Left   => format!(":{}", "-".repeat(w - 1)),
+            Align::Right  => format!("{}:", "-".repeat(w - 1)),
+            Align::Center => format!(":{}:", "-".repeat(w - 2)),
+        }).collect();
+        let mut lines = vec![row(&self.rows[0]), format!("| {} |", delimiter.join(" | "))];
+        lines.extend(self.rows[1..].iter().map(|r| row(r)));
+        (lines, starts)
+    }
+}
+
+// which cell a char offset into a row's line falls in
+fn column_at(line: &str, offset: usize) -> usize {
+    let before: String = line.chars().take(offset).collect();
+    split_row(&format!("{before}x")).len() - 1
+}
+
+// None when the cursor isn't in a table, or the edit makes no sense there
+// (removing the header row, or the last column)
+pub fn edit_table(text: &str, cursor: usize, edit: TableEdit) -> Option<(String, usize)> {
+    let lines: Vec<&str> = text.split('\n').collect();
+    // char offset each line starts at
+    let starts: Vec<usize> = lines.iter().scan(0, |at, l| { let start = *at; *at += l.chars().count() + 1; Some(start) }).collect();
+    let line_no = starts.iter().rposition(|&s| s <= cursor)?;
+    let is_row = |l: &str| l.contains('|');
+    if !is_row(lines[line_no]) {
+        return None;
+    }
+    let first = (0..=line_no).rev().take_while(|&i| is_row(lines[i])).last()?;
+    let last  = (line_no..lines.len()).take_while(|&i| is_row(lines[i])).last()?;
+    let mut table = parse_table(&lines[first..=last])?;
+
+    // position in the table, the delimiter line counts as the header
+    let line_in_table = line_no - first;
+    let mut row = line_in_table.saturating_sub(1);
+    let mut col = column_at(lines[line_no], cursor - starts[line_no]).min(table.aligns.len() - 1);
+    match edit {
+        TableEdit::AddRow => {
+            row += 1;
+            table.rows.insert(row, vec![String::new(); table.aligns.len()]);
+            col = 0;
+        }
+        TableEdit::RemoveRow => {
+            if row == 0 {
+                return None;
+            }
+            table.rows.remove(row);
+            row = row.min(table.rows.len() - 1);
+        }
+        TableEdit::AddColumn => {
+            col += 1;
+            for r in &mut table.rows {
+                r.insert(col, String::new());
+            }
+            table.aligns.insert(col, Align::None);
+        }
+        TableEdit::RemoveColumn => {
+            if table.aligns.len() == 1 {
+                return None;
+            }
+            for r in &mut table.rows {
+                r.remove(col);
+            }
+            table.aligns.remove(col);
+            col = col.min(table.aligns.len() - 1);
+        }
+        TableEdit::Align => {}
+    }
+
+    let (formatted, cell_starts) = table.format();
+    // the header is line 0, body row n is line n + 1
+    let cursor_line = if row == 0 { 0 } else { row + 1 };
+    let cursor = starts[first] + formatted[..cursor_line].iter().map(|l| l.chars().count() + 1).sum::<usize>() + cell_starts[col];
+    let mut out: Vec<String> = lines[..first].iter().map(|l| l.to_string()).collect();
+    out.extend(formatted);
+    out.extend(lines[last + 1..].iter().map(|l| l.to_string()));
+    Some((out.join("\n"), cursor))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSY: &str = "before\n\n|a|b|\n|:-|-:|\n|one|2|\n|three|44|\n\nafter";
+
+    #[test]
+    fn aligns_the_source() {
+        let (text, _) = edit_table(MESSY, 10, TableEdit::Align).unwrap();
+        assert_eq!(
+            text,
+            "before\n\n| a     |   b |\n| :---- | --: |\n| one   |   2 |\n| three |  44 |\n\nafter"
+        );
+    }
+
+    #[test]
+    fn adds_and_removes_rows() {
+        // cursor on the "one" row
+        let at = MESSY.find("one").unwrap();
+        let (text, cursor) = edit_table(MESSY, at, TableEdit::AddRow).unwrap();
+        assert!(text.contains("| one   |   2 |\n|       |     |\n| three |"), "{text}");
+        assert_eq!(text.chars().nth(cursor - 2), Some('|'));
+        let (text, _) = edit_table(&text, cursor, TableEdit::RemoveRow).unwrap();
+        assert_eq!(text, edit_table(MESSY, 10, TableEdit::Align).unwrap().0);
+        // the header can't go
+        assert_eq!(edit_table(MESSY, 10, TableEdit::RemoveRow), None);
+    }
+
+    #[test]
+    fn adds_and_removes_columns() {
+        let at = MESSY.find("one").unwrap();
+        let (text, cursor) = edit_table(MESSY, at, TableEdit::AddColumn).unwrap();
+        assert!(text.contains("| a     |     |   b |\n| :---- | --- | --: |"), "{text}");
+        let (text, _) = edit_table(&text, cursor, TableEdit::RemoveColumn).unwrap();
+        assert_eq!(text, edit_table(MESSY, 10, TableEdit::Align).unwrap().0);
+        let single = "| a |\n| - |";
+        assert_eq!(edit_table(single, 2, TableEdit::RemoveColumn), None);
+    }
+
+    #[test]
+    fn leaves_other_text_alone() {
+        assert_eq!(edit_table(MESSY, 2, TableEdit::Align), None);
+        assert_eq!(edit_table("a | b\nno delimiter", 1, TableEdit::Align), None);
+    }
+
+    #[test]
+    fn keeps_escaped_pipes_and_fills_ragged_rows() {
+        let (text, _) = edit_table("| a | b |\n| - | - |\n| x \\| y |", 0, TableEdit::Align).unwrap();
+        assert_eq!(text, "| a      | b   |\n| ------ | --- |\n| x \\| y |     |");
+        let (text, _) = edit_table(NEW_TABLE, 0, TableEdit::Align).unwrap();
+        assert_eq!(text, NEW_TABLE);
+    }
+}
@@ -1,56 +1,145 @@
+use std::net::{IpAddr, SocketAddr};
 use axum::{
     extract::{
         ws::{Message, WebSocket, WebSocketUpgrade},
-        Path, State,
+        ConnectInfo, Path, State,
     },
-    response::IntoResponse,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
     routing::get,
     Router,
 };
 use futures_util::{SinkExt, StreamExt};
-use serde::{Deserialize, Serialize};
-use tower_http::cors::{Any, CorsLayer};
-use crate::state::AppState;
+use reality::protocol::{ClientMsg, ServerMsg};
+use crate::auth::Caller;
+use reality::protocol::Role;
+use reality::doc::MergeError;
+use tokio::sync::mpsc;
+use crate::limits::Limits;
+use crate::rate::{client_ip, SocketSlot, Throttle};
+use crate::state::{AppState, Room};
 
 pub fn ws_router(state: AppState) -> Router {
-    let cors = CorsLayer::new()
-        .allow_origin(Any)
-        .allow_headers(Any)
-        .allow_methods(Any);
     Router::new()
         .route("/ws/*id", get(ws_handler))
-        .layer(cors)
+        .route("/ws-view/:token", get(ws_view_handler))
         .with_state(state)
 }
 
-// server and client side handling of the text changes
-#[derive(Deserialize)]
-struct ClientMsg {
-    client_id: String,
-    changes:   Vec<u8>,
+// who is on the other end of a socket, decided once at upgrade time
+struct Connection {
+    caller:    Caller,
+    // came in through a view link, so never more than a viewer whatever the acl says
+    view_only: bool,
 }
 
-#[derive(Serialize)]
-struct ServerMsg {
-    sender_id: String,
-    text:      String,
-    full_doc:  Vec<u8>,
+impl Connection {
+    // checked again for every change so taking someone's access away works mid session
+    async fn role(&self, id: &str, room: &Room) -> Option<Role> {
+        if self.view_only {
+            return Some(Role::Viewer);
+        }
+        self.caller.role(id, &*room.meta.lock().await)
+    }
 }
 
 pub async fn ws_handler(
     ws: WebSocketUpgrade,
     Path(id): Path<String>,
     State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    caller: Caller,
 ) -> impl IntoResponse {
     let id = id.trim_start_matches('/').to_string();
-    ws.on_upgrade(move |socket| handle_socket(socket, id, state))
+    // a doc token can only ever open its own doc, so it never gets to create one
+    let room = match &caller.token {
+        Some(_) => match state.get_room(&id) {
+            Some(room) => room,
+            None       => return StatusCode::NOT_FOUND.into_response(),
+        },
+        None => state.get_or_create_room(&id, caller.user.as_ref()),
+    };
+    let conn = Connection { caller, view_only: false };
+    if conn.role(&id, &room).await.is_none() {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+    if conn.caller.needs_password(&id, &*room.meta.lock().await, &state.passes) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    upgrade(ws, &state, id, room, conn, client_ip(addr, &headers, &state.limits))
+}
+
+// same room as /ws/:id but anything the client sends is thrown away. a doc password
+// doesn't apply here, the view token is its own secret
+pub async fn ws_view_handler(
+    ws: WebSocketUpgrade,
+    Path(token): Path<String>,
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    caller: Caller,
+) -> impl IntoResponse {
+    let Some(id) = state.doc_for_view_token(&token) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let Some(room) = state.get_room(&id) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let conn = Connection { caller, view_only: true };
+    upgrade(ws, &state, id, room, conn, client_ip(addr, &headers, &state.limits))
+}
+
+// anything up to twice the frame limit gets read so the client can be told what went wrong,
+// past that the socket is just dropped rather than buffering whatever was sent
+fn upgrade(ws: WebSocketUpgrade, state: &AppState, id: String, room: Room, conn: Connection, ip: IpAddr) -> Response {
+    let limits = state.limits;
+    let ws = ws.max_message_size(limits.max_frame_bytes.saturating_mul(2));
+    // a browser never sees the status of a failed upgrade, so over the caps the socket
+    // still opens, just long enough to say why
+    let refusal = if room.tx.receiver_count() >= limits.max_sockets_per_doc {
+        Err(limits.doc_full_message())
+    } else {
+        state.rates.open_socket(ip, &limits).ok_or_else(|| limits.too_many_sockets_message())
+    };
+    match refusal {
+        Ok(slot) => ws
+            .on_upgrade(move |socket| handle_socket(socket, id, room, conn, slot, limits))
+            .into_response(),
+        Err(message) => {
+            eprintln!("[ws/{id}] refused socket from {ip}: {message}");
+            ws.on_upgrade(move |socket| refuse_socket(socket, message)).into_response()
+        }
+    }
+}
+
+async fn refuse_socket(mut socket: WebSocket, message: String) {
+    let payload = serde_json::to_string(&ServerMsg::error(message)).unwrap();
+    let _ = socket.send(Message::Text(payload)).await;
+    let _ = socket.close().await;
+}
+
+// the error, then the server's copy of the doc so the client drops the edit it already made locally
+async fn rejection(room: &Room, message: String) -> [String; 2] {
+    let mut doc = room.doc.lock().await;
+    let resync = ServerMsg {
+        sender_id: "server".to_string(),
+        text:      doc.get_text(),
+        full_doc:  doc.save(),
+        error:     None,
+    };
+    [
+        serde_json::to_string(&ServerMsg::error(message)).unwrap(),
+        serde_json::to_string(&resync).unwrap(),
+    ]
 }
 
 // this is all the server shit when it comes to communicating the text payload, and saving the .md so it doesn't get erased in memory if the server needs a restart
-async fn handle_socket(socket: WebSocket, id: String, state: AppState) {
-    let room = state.get_or_create_room(&id);
+async fn handle_socket(socket: WebSocket, id: String, room: Room, conn: Connection, slot: SocketSlot, limits: Limits) {
     let mut rx = room.tx.subscribe();
     let (mut sink, mut stream) = socket.split();
+    // for messages meant only for this client, the room broadcast goes to everyone
+    let (direct_tx, mut direct_rx) = mpsc::unbounded_channel::<String>();
 
     {
         let mut doc = room.doc.lock().await;
@@ -58,13 +147,18 @@ async fn handle_socket(socket: WebSocket, id: String, state: AppState) {
             sender_id: "server".to_string(),
             text:      doc.get_text(),
             full_doc:  (*doc).save(),
+            error:     None,
         }).unwrap();
-        let _ = sink.send(Message::Text(payload.into())).await;
+        let _ = sink.send(Message::Text(payload)).await;
     }
 
     let mut send_task = tokio::spawn(async move {
-        while let Ok(payload) = rx.recv().await {
-            if sink.send(Message::Text(payload.into())).await.is_err() {
+        loop {
+            let payload = tokio::select! {
+                msg = rx.recv()        => match msg { Ok(p) => p, Err(_) => break },
+                msg = direct_rx.recv() => match msg { Some(p) => p, None => break },
+            };
+            if sink.send(Message::Text(payload)).await.is_err() {
                 break;
             }
         }
@@ -73,6 +167,7 @@ async fn handle_socket(socket: WebSocket, id: String, state: AppState) {
     let room_clone = room.clone();
     let id_clone = id.clone();
     let mut recv_task = tokio::spawn(async move {
+        let mut throttle = Throttle::per_socket(&limits);
         while let Some(Ok(msg)) = stream.next().await {
             let bytes = match msg {
                 Message::Text(t)   => t.into_bytes(),
@@ -81,6 +176,35 @@ async fn handle_socket(socket: WebSocket, id: String, state: AppState) {
                 _                  => continue,
             };
 
+            // over the rate the change is held rather than dropped, and since nothing more is
+            // read off the socket meanwhile a flooding tab ends up waiting on itself
+            let wait = throttle.take(bytes.len()).max(slot.take(bytes.len()));
+            if !wait.is_zero() {
+                let notice = ServerMsg::throttled(limits.throttled_message(wait));
+                let _ = direct_tx.send(serde_json::to_string(&notice).unwrap());
+                tokio::time::sleep(wait).await;
+            }
+
+            if !conn.role(&id_clone, &room_clone).await.is_some_and(Role::can_edit) {
+                eprintln!("[ws/{id_clone}] dropped change from a connection without edit access");
+                continue;
+            }
+
+            let refusal = if bytes.len() > limits.max_frame_bytes {
+                Some(limits.too_big_message(bytes.len()))
+            } else if room_clone.history_full(&limits) {
+                Some(limits.history_full_message())
+            } else {
+                None
+            };
+            if let Some(message) = refusal {
+                eprintln!("[ws/{id_clone}] refused change: {message}");
+                for payload in rejection(&room_clone, message).await {
+                    let _ = direct_tx.send(payload);
+                }
+                continue;
+            }
+
             let client_msg: ClientMsg = match serde_json::from_slice(&bytes) {
                 Ok(c) => c,
                 Err(e) => {
@@ -89,36 +213,56 @@ async fn handle_socket(socket: WebSocket, id: String, state: AppState) {
                 }
             };
 
-            let (new_text, full_doc) = {
+            let (new_text, full_doc, actors) = {
                 let mut doc = room_clone.doc.lock().await;
-                let text = doc.merge_changes(&client_msg.changes);
+                let before = doc.heads();
+                let text = match doc.merge_changes_within(&client_msg.changes, limits.max_doc_chars) {
+                    Ok(text) => text,
+                    Err(MergeError::Invalid) => {
+                        eprintln!("[ws/{id_clone}] changes weren't valid automerge, dropped");
+                        continue;
+                    }
+                    Err(MergeError::TooLong) => {
+                        drop(doc);
+                        eprintln!("[ws/{id_clone}] refused change that would go over {} characters", limits.max_doc_chars);
+                        for payload in rejection(&room_clone, limits.too_long_message()).await {
+                            let _ = direct_tx.send(payload);
+                        }
+                        continue;
+                    }
+                };
                 eprintln!("[ws/{id_clone}] merged, text len={}", text.len());
+                let actors = doc.actors_since(&before);
                 let full = (*doc).save();
-                (text, full)
+                (text, full, actors)
             };
 
-            let text_for_save    = new_text.clone();
-            let full_doc_for_save = full_doc.clone();
-            let id_for_save      = id_clone.clone();
-            tokio::spawn(async move {
-                if let Err(e) = std::fs::create_dir_all("docs") {
-                    eprintln!("create_dir_all failed: {e}");
-                    return;
-                }
-                let path = format!("docs/{id_for_save}.md");
-                if let Err(e) = std::fs::write(&path, &text_for_save) {
-                    eprintln!("write {path} failed: {e}");
+            // a signed in account beats whatever name the client typed in
+            let name = match &conn.caller.user {
+                Some(user) => Some(user.username.as_str()),
+                None       => client_msg.name.as_deref().map(str::trim).filter(|n| !n.is_empty()),
+            };
+            if let Some(name) = name {
+                let mut meta = room_clone.meta.lock().await;
+                let mut changed = false;
+                for actor in actors {
+                    if meta.authors.get(&actor).map(String::as_str) != Some(name) {
+                        meta.authors.insert(actor, name.to_string());
+                        changed = true;
+                    }
                 }
-                let am_path = format!("docs/{id_for_save}.am");
-                if let Err(e) = std::fs::write(&am_path, &full_doc_for_save) {
-                    eprintln!("write {am_path} failed: {e}");
+                if changed {
+                    meta.persist(&id_clone);
                 }
-            });
+            }
+
+            room_clone.persist(&id_clone, new_text.clone(), full_doc.clone());
 
             let payload = serde_json::to_string(&ServerMsg {
                 sender_id: client_msg.client_id,
                 text:      new_text,
                 full_doc,
+                error:     None,
             }).unwrap();
             let _ = room_clone.tx.send(payload);
         }
@@ -128,4 +272,25 @@ async fn handle_socket(socket: WebSocket, id: String, state: AppState) {
         _ = &mut send_task => recv_task.abort(),
         _ = &mut recv_task => send_task.abort(),
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::meta::DocMeta;
+
+    // the password screen is for /ws/:id, a view link is a secret of its own and gets read access
+    #[tokio::test]
+    async fn view_links_skip_the_doc_password() {
+        let state = AppState::in_memory();
+        let meta  = DocMeta { password_hash: Some("set".into()), link_role: None, ..DocMeta::default() };
+        let room  = Room::new("secret").with_meta(meta);
+        let stranger = Caller::for_test(&state, &[]).await;
+        assert!(stranger.needs_password("notes", &*room.meta.lock().await, &state.passes));
+
+        let by_link = Connection { caller: stranger.clone(), view_only: false };
+        assert_eq!(by_link.role("notes", &room).await, None);
+        let by_view_link = Connection { caller: stranger, view_only: true };
+        assert_eq!(by_view_link.role("notes", &room).await, Some(Role::Viewer));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,63 @@
+// the examples from the commonmark 0.31.2 spec (https://spec.commonmark.org/0.31.2/,
+// CC-BY-SA 4.0), run through the preview renderer
+use pulldown_cmark::{Event, Parser, Tag};
+use reality::markdown::{is_safe_url, render_markdown};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct Example {
+    example:  u32,
+    markdown: String,
+    html:     String,
+}
+
+// raw html is deliberately shown as text and unsafe links as their text, so examples
+// exercising those can't match the spec output and are left out
+fn runs_as_spec(md: &str) -> bool {
+    Parser::new(md).all(|event| match event {
+        Event::Html(_) | Event::InlineHtml(_)       => false,
+        Event::Start(Tag::Link { dest_url, .. })   => is_safe_url(&dest_url),
+        Event::Start(Tag::Image { dest_url, .. })  => is_safe_url(&dest_url),
+        _                                          => true,
+    })
+}
+
+// the ids we give every heading, which the spec doesn't have
+fn strip_heading_ids(html: &str) -> String {
+    let mut out  = String::new();
+    let mut rest = html;
+    while let Some(at) = rest.find(" id=\"") {
+        let tag_start = rest[..at].rfind('<').unwrap_or(0);
+        let is_heading = rest[tag_start..at].len() == 3 && rest[tag_start..].starts_with("<h");
+        let end = at + 5 + rest[at + 5..].find('"').map_or(0, |i| i + 1);
+        out.push_str(&rest[..if is_heading { at } else { end }]);
+        rest = &rest[end..];
+    }
+    out.push_str(rest);
+    out
+}
+
+// same leeway the spec's own runner gives, plus the attributes we add to every link and heading
+fn normalize(html: &str) -> String {
+    strip_heading_ids(html)
+        .replace(" target=\"_blank\" rel=\"noopener noreferrer\"", "")
+        .replace("<br>", "<br />")
+        .replace("<hr>", "<hr />")
+        .replace(">\n<", "><")
+}
+
+#[test]
+fn commonmark_spec() {
+    let examples: Vec<Example> = serde_json::from_str(include_str!("commonmark_spec.json")).unwrap();
+    let mut ran      = 0;
+    let mut failures = Vec::new();
+    for ex in examples.iter().filter(|ex| runs_as_spec(&ex.markdown)) {
+        ran += 1;
+        let got = render_markdown(&ex.markdown);
+        if normalize(&got) != normalize(&ex.html) {
+            failures.push(format!("example {}:\n{:?}\nexpected {:?}\ngot      {:?}", ex.example, ex.markdown, ex.html, got));
+        }
+    }
+    assert!(ran > 500, "only {ran} of {} examples ran", examples.len());
+    assert!(failures.is_empty(), "{} of {ran} spec examples failed\n\n{}", failures.len(), failures.join("\n\n"));
+}